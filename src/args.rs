@@ -0,0 +1,351 @@
+//! The i3lock and i3lock-color options passed through after `--`.
+
+use std::fmt;
+
+use crate::lock::LockError;
+use crate::Error;
+
+/// An option either locker understands.
+struct Spec {
+    short: Option<char>,
+    long: &'static str,
+    value: bool,
+    color_only: bool,
+}
+
+const fn opt(short: Option<char>, long: &'static str, value: bool) -> Spec {
+    Spec {
+        short,
+        long,
+        value,
+        color_only: false,
+    }
+}
+
+const fn color(short: Option<char>, long: &'static str, value: bool) -> Spec {
+    Spec {
+        short,
+        long,
+        value,
+        color_only: true,
+    }
+}
+
+/// Options of i3lock 2.12 and later, then i3lock-color's short ones and long ones that
+/// don't take a value.
+///
+/// Any other option is assumed to be i3lock-color's. A short one doesn't take a value,
+/// and a long one takes the next argument unless that looks like an option, so a value
+/// starting with "-" has to be given as "--name=value".
+const SPECS: [Spec; 40] = [
+    opt(Some('v'), "version", false),
+    opt(Some('n'), "nofork", false),
+    opt(Some('b'), "beep", false),
+    opt(Some('d'), "dpms", false),
+    opt(Some('c'), "color", true),
+    opt(Some('p'), "pointer", true),
+    opt(None, "debug", false),
+    opt(Some('h'), "help", false),
+    opt(Some('u'), "no-unlock-indicator", false),
+    opt(Some('i'), "image", true),
+    opt(None, "raw", true),
+    opt(Some('t'), "tiling", false),
+    opt(Some('e'), "ignore-empty-password", false),
+    opt(Some('I'), "inactivity-timeout", true),
+    opt(Some('f'), "show-failed-attempts", false),
+    opt(Some('k'), "show-keyboard-layout", false),
+    color(Some('C'), "centered", false),
+    color(Some('F'), "fill", false),
+    color(Some('M'), "max", false),
+    color(Some('L'), "scale", false),
+    color(Some('r'), "line-uses-ring", false),
+    color(Some('s'), "line-uses-inside", false),
+    color(None, "clock", false),
+    color(None, "force-clock", false),
+    color(None, "indicator", false),
+    color(None, "no-verify", false),
+    color(None, "composite", false),
+    color(None, "redraw-thread", false),
+    color(None, "no-modkey-text", false),
+    color(None, "pass-media-keys", false),
+    color(None, "pass-screen-keys", false),
+    color(None, "pass-power-keys", false),
+    color(None, "pass-volume-keys", false),
+    color(None, "special-passthrough", false),
+    color(None, "bar-indicator", false),
+    color(None, "bar-reversed", false),
+    color(None, "slideshow-random-selection", false),
+    color(None, "custom-key-commands", false),
+    color(Some('B'), "blur", true),
+    color(Some('S'), "screen", true),
+];
+
+/// Short options i3lock-color gives another meaning, looked up before [`SPECS`] for it.
+const COLOR_SPECS: [Spec; 1] = [color(Some('k'), "clock", false)];
+
+/// Options that would make i3lock use another image, or exit without locking.
+const CONFLICTS: [&str; 4] = ["image", "raw", "version", "help"];
+
+/// One passed through option, by its long name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Opt {
+    /// Long name without the dashes, e.g. "nofork", or the letter of an unknown short option
+    pub name: String,
+    /// The letter it was given as, if it was given short
+    pub short: Option<char>,
+    /// Its value, if it takes one
+    pub value: Option<String>,
+    /// Whether only i3lock-color has it
+    pub color_only: bool,
+}
+
+impl fmt::Display for Opt {
+    /// As it was given, e.g. "-n" or "--nofork"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.short {
+            Some(c) => write!(f, "-{c}"),
+            None => write!(f, "--{}", self.name),
+        }
+    }
+}
+
+/// Options for i3lock or i3lock-color, parsed the way their getopt does.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct I3lockArgs {
+    /// Every option given, in order
+    pub opts: Vec<Opt>,
+}
+
+impl I3lockArgs {
+    /// Parse the arguments after `--` as i3lock's, stopping at another `--`.
+    pub fn parse<S: AsRef<str>>(args: &[S]) -> Result<Self, Error> {
+        Self::parse_as(args, false)
+    }
+
+    /// Parse the arguments after `--` as i3lock-color's, stopping at another `--`.
+    pub fn parse_color<S: AsRef<str>>(args: &[S]) -> Result<Self, Error> {
+        Self::parse_as(args, true)
+    }
+
+    fn parse_as<S: AsRef<str>>(args: &[S], color: bool) -> Result<Self, Error> {
+        let mut opts = Vec::new();
+        let mut args = args.iter().map(AsRef::as_ref).peekable();
+        let missing = |arg: &str| LockError::Args(format!("i3lock option \"{arg}\" needs a value"));
+
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                break;
+            } else if let Some(long) = arg.strip_prefix("--") {
+                let (name, inline) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (long, None),
+                };
+                let spec = SPECS.iter().find(|spec| spec.long == name);
+                let value = match (spec.map(|spec| spec.value), inline) {
+                    (Some(false), Some(_)) => {
+                        return Err(LockError::Args(format!(
+                            "i3lock option \"--{name}\" doesn't take a value"
+                        ))
+                        .into())
+                    }
+                    (Some(false), None) => None,
+                    (_, Some(value)) => Some(value.to_string()),
+                    (Some(true), None) => {
+                        Some(args.next().ok_or_else(|| missing(arg))?.to_string())
+                    }
+                    (None, None) => args
+                        .next_if(|next| !next.starts_with('-'))
+                        .map(str::to_string),
+                };
+                opts.push(Opt {
+                    name: name.to_string(),
+                    short: None,
+                    value,
+                    color_only: spec.is_none_or(|spec| spec.color_only),
+                });
+            } else if let Some(shorts) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+                let specs = if color { &COLOR_SPECS[..] } else { &[] };
+                for (i, c) in shorts.char_indices() {
+                    let Some(spec) = specs
+                        .iter()
+                        .chain(&SPECS)
+                        .find(|spec| spec.short == Some(c))
+                    else {
+                        opts.push(Opt {
+                            name: c.to_string(),
+                            short: Some(c),
+                            value: None,
+                            color_only: true,
+                        });
+                        continue;
+                    };
+                    // the value is the rest of this argument, or the next one
+                    let rest = &shorts[i + c.len_utf8()..];
+                    let value = match (spec.value, rest) {
+                        (false, _) => None,
+                        (true, "") => Some(args.next().ok_or_else(|| missing(arg))?.to_string()),
+                        (true, rest) => Some(rest.to_string()),
+                    };
+                    opts.push(Opt {
+                        name: spec.long.to_string(),
+                        short: Some(c),
+                        value,
+                        color_only: spec.color_only,
+                    });
+                    if spec.value {
+                        break;
+                    }
+                }
+            }
+            // getopt skips anything else, which i3lock then ignores
+        }
+
+        Ok(Self { opts })
+    }
+
+    /// The last value given for the option called `name`, like getopt would use.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.opts
+            .iter()
+            .rev()
+            .find(|opt| opt.name == name)
+            .and_then(|opt| opt.value.as_deref())
+    }
+
+    /// Whether the option called `name` was given.
+    pub fn has(&self, name: &str) -> bool {
+        self.opts.iter().any(|opt| opt.name == name)
+    }
+
+    /// `-n`, `--nofork`: stay until unlocked instead of forking once locked.
+    pub fn nofork(&self) -> bool {
+        self.has("nofork")
+    }
+
+    /// `-i`, `--image`: the image to show.
+    pub fn image(&self) -> Option<&str> {
+        self.get("image")
+    }
+
+    /// `-c`, `--color`: the color around the image, as "rrggbb".
+    pub fn color(&self) -> Option<&str> {
+        self.get("color")
+    }
+
+    /// `-e`, `--ignore-empty-password`
+    pub fn ignore_empty_password(&self) -> bool {
+        self.has("ignore-empty-password")
+    }
+
+    /// `-t`, `--tiling`: tile the image instead of showing it once.
+    pub fn tiling(&self) -> bool {
+        self.has("tiling")
+    }
+
+    /// The first option only i3lock-color has.
+    pub fn color_only(&self) -> Option<&Opt> {
+        self.opts.iter().find(|opt| opt.color_only)
+    }
+
+    /// Reject options that clash with how i3lockr runs the locker called `name`.
+    pub fn check(&self, name: &str) -> Result<(), Error> {
+        match self
+            .opts
+            .iter()
+            .find(|opt| CONFLICTS.contains(&opt.name.as_str()))
+        {
+            Some(opt) if opt.name == "image" || opt.name == "raw" => Err(LockError::Args(format!(
+                "{name}: \"{opt}\" can't be used, i3lockr passes the image itself"
+            ))
+            .into()),
+            Some(opt) => Err(LockError::Args(format!(
+                "{name}: \"{opt}\" can't be used, it would exit without locking"
+            ))
+            .into()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> I3lockArgs {
+        I3lockArgs::parse(args).unwrap()
+    }
+
+    #[test]
+    fn values() {
+        let args = parse(&["-nec", "n0f0rk", "--pointer", "win", "-I5", "--raw=1x1:rgb"]);
+        assert!(args.nofork());
+        assert!(args.ignore_empty_password());
+        assert_eq!(args.color(), Some("n0f0rk"));
+        assert_eq!(args.get("pointer"), Some("win"));
+        assert_eq!(args.get("inactivity-timeout"), Some("5"));
+        assert_eq!(args.get("raw"), Some("1x1:rgb"));
+        assert_eq!(args.opts.len(), 6);
+
+        // the value of -i is not an option, even when it looks like one
+        let args = parse(&["-i", "-n.png", "--image", "--nofork"]);
+        assert!(!args.nofork());
+        assert_eq!(args.image(), Some("--nofork"));
+
+        assert!(I3lockArgs::parse(&["-c"]).is_err());
+        assert!(I3lockArgs::parse(&["--nofork=yes"]).is_err());
+    }
+
+    #[test]
+    fn color_only() {
+        assert_eq!(parse(&["-ec", "ffffff", "--tiling"]).color_only(), None);
+        let args = parse(&["-e", "--clock", "--time-str", "%H:%M", "-n"]);
+        assert!(args.nofork());
+        assert_eq!(args.get("time-str"), Some("%H:%M"));
+
+        // unknown long options only take the next argument if it isn't an option
+        assert!(parse(&["--line-uses-ring", "--nofork"]).nofork());
+        assert!(parse(&["--keylayout", "--nofork"]).nofork());
+        let dates = parse(&["--date-str=-%d", "--abc", "--", "-n"]);
+        assert_eq!(dates.get("date-str"), Some("-%d"));
+        assert_eq!(dates.get("abc"), None);
+        assert!(!dates.nofork());
+        assert!(parse(&["-rs"]).color_only().is_some());
+        assert_eq!(
+            args.color_only().map(|opt| opt.name.as_str()),
+            Some("clock")
+        );
+        assert!(parse(&["-eB", "5"]).color_only().is_some());
+        assert_eq!(parse(&["--", "--clock"]).color_only(), None);
+
+        // unknown short options are taken to be i3lock-color's, like unknown long ones
+        let args = parse(&["-an"]);
+        assert!(args.nofork());
+        assert_eq!(
+            args.color_only().map(ToString::to_string).as_deref(),
+            Some("-a")
+        );
+    }
+
+    #[test]
+    fn flavors() {
+        let args = parse(&["-k"]);
+        assert!(args.has("show-keyboard-layout"));
+        assert_eq!(args.color_only(), None);
+
+        let args = I3lockArgs::parse_color(&["-ek"]).unwrap();
+        assert!(args.has("clock"));
+        assert!(!args.has("show-keyboard-layout"));
+        assert_eq!(
+            args.color_only().map(ToString::to_string).as_deref(),
+            Some("-k")
+        );
+    }
+
+    #[test]
+    fn conflicts() {
+        assert!(parse(&["-n", "--color=000000"]).check("i3lock").is_ok());
+        assert!(parse(&["-ei", "lock.png"]).check("i3lock").is_err());
+        assert!(parse(&["--raw", "1x1:rgb"]).check("i3lock").is_err());
+        assert!(parse(&["-v"]).check("i3lock").is_err());
+    }
+}
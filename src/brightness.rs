@@ -1,3 +1,5 @@
+//! Brightening and darkening the screenshot.
+
 use std::num::NonZeroU8;
 
 use imgref::ImgRefMut;
@@ -8,8 +10,11 @@ use rayon::prelude::*;
 use rgb::alt::BGRA8;
 use rgb::ColorComponentMap;
 
+/// Uniform brightness adjustment, saturating at black and white.
 pub trait BrightnessAdj {
+    /// Add `amt` to every color channel.
     fn brighten(&mut self, amt: NonZeroU8);
+    /// Subtract `amt` from every color channel.
     fn darken(&mut self, amt: NonZeroU8);
 }
 
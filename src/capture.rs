@@ -0,0 +1,633 @@
+//! Taking the screenshot, with a choice of backends.
+
+use std::fs::{self, File as FsFile};
+use std::io::{self, ErrorKind::WouldBlock, Read, Write};
+use std::ops::{Deref, DerefMut};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use std::{error, fmt};
+
+use imgref::{ImgRef, ImgRefMut, ImgVec};
+use rgb::alt::BGRA8;
+use rgb::{ComponentBytes, FromSlice};
+use xcb::{x, Connection, CookieWithReplyChecked};
+
+use crate::secret::{self, Secret};
+use crate::Error;
+
+/// Screen capture backends in the order [`detect`] tries them.
+pub const BACKENDS: [&str; 3] = ["scrap", "shm", "get-image"];
+
+/// A way of getting the image to lock with.
+pub trait CaptureBackend {
+    /// The name used with `--capture-backend`
+    fn name(&self) -> &'static str;
+
+    /// Capture the whole screen, giving up after `timeout`
+    fn capture(&mut self, timeout: Duration) -> Result<Screenshot<'_>, Error>;
+}
+
+/// Why the screen couldn't be captured.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CaptureError {
+    /// No such backend, or one that can't be used with the other options
+    Backend(String),
+    /// The X server can't be captured this way, e.g. it lacks MIT-SHM
+    Unsupported(String),
+    /// Setting up the backend failed
+    Setup(io::Error),
+    /// Capturing a frame failed
+    Frame(io::Error),
+    /// No frame arrived within the timeout
+    Timeout(Duration),
+    /// Every backend tried by [`detect`] failed, with why
+    NoBackend(Vec<(&'static str, Error)>),
+    /// The image saved with [`save_cache`] is damaged
+    Cache(String),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Backend(e) | Self::Unsupported(e) => f.write_str(e),
+            Self::Cache(e) => write!(f, "bad cached image: {e}"),
+            Self::Setup(e) => write!(f, "setup failed: {e}"),
+            Self::Frame(e) => write!(f, "no frame: {e}"),
+            Self::Timeout(timeout) => write!(f, "no frame within {timeout:?}"),
+            Self::NoBackend(errors) => {
+                f.write_str("no backend works")?;
+                for (i, (name, e)) in errors.iter().enumerate() {
+                    let sep = if i == 0 { " (" } else { "; " };
+                    match e {
+                        Error::Capture(e) => write!(f, "{sep}{name}: {e}")?,
+                        e => write!(f, "{sep}{name}: {e}")?,
+                    }
+                }
+                if !errors.is_empty() {
+                    f.write_str(")")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl error::Error for CaptureError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Setup(e) | Self::Frame(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<CaptureError> for Error {
+    fn from(e: CaptureError) -> Self {
+        Self::Capture(e)
+    }
+}
+
+/// A captured image, borrowed from the backend where that saves a copy.
+pub struct Screenshot<'a> {
+    buf: Box<dyn DerefMut<Target = [BGRA8]> + 'a>,
+    width: usize,
+    height: usize,
+    locked: bool,
+}
+
+impl<'a> Screenshot<'a> {
+    /// Wrap `width * height` pixels, locking them into memory if possible.
+    /// They're wiped when the screenshot is dropped.
+    pub fn new<B>(buf: B, width: usize, height: usize) -> Self
+    where
+        B: DerefMut<Target = [BGRA8]> + 'a,
+    {
+        let locked = secret::lock(&buf).is_ok();
+        Self {
+            buf: Box::new(buf),
+            width,
+            height,
+            locked,
+        }
+    }
+
+    /// The image, ready for effects and overlays.
+    pub fn as_img(&mut self) -> ImgRefMut<'_, BGRA8> {
+        ImgRefMut::new(&mut self.buf, self.width, self.height)
+    }
+
+    /// Whether the pixels are locked into memory.
+    pub const fn locked(&self) -> bool {
+        self.locked
+    }
+}
+
+impl Drop for Screenshot<'_> {
+    fn drop(&mut self) {
+        secret::wipe(&mut self.buf);
+        if self.locked {
+            secret::unlock(&self.buf);
+        }
+    }
+}
+
+/// Open the backend called `name`, one of [`BACKENDS`].
+pub fn open(name: &str) -> Result<Box<dyn CaptureBackend>, Error> {
+    match name {
+        "scrap" => Ok(Box::new(Scrap::new()?)),
+        #[cfg(feature = "shm")]
+        "shm" => Ok(Box::new(Shm::new()?)),
+        #[cfg(not(feature = "shm"))]
+        "shm" => Err(CaptureError::Backend(
+            "feature \"shm\" was not enabled at compile-time".to_string(),
+        )
+        .into()),
+        "get-image" => Ok(Box::new(GetImage::new()?)),
+        _ => Err(CaptureError::Backend(format!(
+            "unknown capture backend \"{name}\", expected one of: {}",
+            BACKENDS.join(", ")
+        ))
+        .into()),
+    }
+}
+
+/// Open the first of [`BACKENDS`] that works on this X server.
+pub fn detect() -> Result<Box<dyn CaptureBackend>, Error> {
+    let mut errors = Vec::new();
+    for name in BACKENDS {
+        match open(name) {
+            Ok(backend) => return Ok(backend),
+            Err(e) => errors.push((name, e)),
+        }
+    }
+    Err(CaptureError::NoBackend(errors).into())
+}
+
+/// The primary display through scrap, which uses MIT-SHM.
+pub struct Scrap(scrap::Capturer);
+
+impl Scrap {
+    /// Set up capture of the primary display.
+    ///
+    /// Scrap always captures the first X screen, so this fails when `DISPLAY` asks for another.
+    pub fn new() -> Result<Self, Error> {
+        let (_, screen_num) = Connection::connect(None)?;
+        if screen_num != 0 {
+            return Err(CaptureError::Unsupported(format!(
+                "scrap can only capture X screen 0, not {screen_num}"
+            ))
+            .into());
+        }
+        let display = scrap::Display::primary().map_err(CaptureError::Setup)?;
+        let capturer = scrap::Capturer::new(display).map_err(CaptureError::Setup)?;
+        Ok(Self(capturer))
+    }
+}
+
+/// The capturer's own shared memory, which is wiped in place with the [`Screenshot`].
+struct ScrapFrame<'a>(scrap::Frame<'a>);
+
+impl Deref for ScrapFrame<'_> {
+    type Target = [BGRA8];
+
+    fn deref(&self) -> &[BGRA8] {
+        self.0.as_bgra()
+    }
+}
+
+impl DerefMut for ScrapFrame<'_> {
+    fn deref_mut(&mut self) -> &mut [BGRA8] {
+        self.0.as_bgra_mut()
+    }
+}
+
+impl CaptureBackend for Scrap {
+    fn name(&self) -> &'static str {
+        "scrap"
+    }
+
+    fn capture(&mut self, timeout: Duration) -> Result<Screenshot<'_>, Error> {
+        let (w, h) = (self.0.width(), self.0.height());
+        let capturer: *mut scrap::Capturer = &mut self.0;
+        let start = Instant::now();
+        loop {
+            // SAFETY: only a successful frame is kept, failed attempts don't
+            // borrow the capturer past the match, which the borrow checker can't see
+            match unsafe { &mut *capturer }.frame() {
+                Ok(frame) => return Ok(Screenshot::new(ScrapFrame(frame), w, h)),
+                Err(e) if e.kind() == WouldBlock => {
+                    if start.elapsed() >= timeout {
+                        return Err(CaptureError::Timeout(timeout).into());
+                    }
+                    sleep(Duration::from_millis(33));
+                }
+                Err(e) => return Err(CaptureError::Frame(e).into()),
+            }
+        }
+    }
+}
+
+/// Wait for the reply to `cookie`, giving up after `timeout`.
+fn reply_within<C>(conn: &Connection, cookie: C, timeout: Duration) -> Result<C::Reply, Error>
+where
+    C: CookieWithReplyChecked,
+{
+    conn.flush()?;
+    let start = Instant::now();
+    loop {
+        if let Some(reply) = conn.poll_for_reply(&cookie) {
+            return Ok(reply?);
+        }
+        if start.elapsed() >= timeout {
+            return Err(CaptureError::Timeout(timeout).into());
+        }
+        sleep(Duration::from_millis(1));
+    }
+}
+
+/// The X screen's root window, with its size.
+fn root(conn: &Connection, screen_num: i32) -> Result<(x::Window, u16, u16), Error> {
+    let screen = usize::try_from(screen_num)
+        .ok()
+        .and_then(|n| conn.get_setup().roots().nth(n))
+        .unwrap_or_else(|| unreachable!());
+
+    // anything else isn't laid out as BGRA
+    if !matches!(screen.root_depth(), 24 | 32) {
+        return Err(CaptureError::Unsupported(format!(
+            "unsupported screen depth {}, expected 24 or 32",
+            screen.root_depth()
+        ))
+        .into());
+    }
+    Ok((
+        screen.root(),
+        screen.width_in_pixels(),
+        screen.height_in_pixels(),
+    ))
+}
+
+/// The default X screen through a MIT-SHM segment shared with the X server.
+/// Unlike scrap this works on any screen, e.g. `DISPLAY=:0.1`.
+#[cfg(feature = "shm")]
+pub struct Shm {
+    conn: Connection,
+    root: x::Window,
+    w: u16,
+    h: u16,
+    seg: xcb::shm::Seg,
+    addr: *mut libc::c_void,
+}
+
+#[cfg(feature = "shm")]
+impl Shm {
+    /// Connect to X and share a segment the size of the screen.
+    pub fn new() -> Result<Self, Error> {
+        let (conn, screen_num) =
+            Connection::connect_with_extensions(None, &[], &[xcb::Extension::Shm])?;
+        if !conn
+            .active_extensions()
+            .any(|ext| ext == xcb::Extension::Shm)
+        {
+            return Err(CaptureError::Unsupported(
+                "the X server doesn't support MIT-SHM".to_string(),
+            )
+            .into());
+        }
+        let (root, w, h) = root(&conn, screen_num)?;
+
+        let size = usize::from(w) * usize::from(h) * 4;
+        // SAFETY: plain System V shared memory calls, every result is checked
+        let (shmid, addr) = unsafe {
+            let shmid = libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600);
+            if shmid == -1 {
+                return Err(CaptureError::Setup(io::Error::last_os_error()).into());
+            }
+            let addr = libc::shmat(shmid, std::ptr::null(), 0);
+            if addr as isize == -1 {
+                let e = io::Error::last_os_error();
+                libc::shmctl(shmid, libc::IPC_RMID, std::ptr::null_mut());
+                return Err(CaptureError::Setup(e).into());
+            }
+            (shmid, addr)
+        };
+
+        let seg = conn.generate_id();
+        let attached = conn.send_and_check_request(&xcb::shm::Attach {
+            shmseg: seg,
+            shmid: shmid.unsigned_abs(),
+            read_only: false,
+        });
+        // SAFETY: once the X server has attached, or failed to, the id isn't needed
+        unsafe {
+            libc::shmctl(shmid, libc::IPC_RMID, std::ptr::null_mut());
+        }
+        if let Err(e) = attached {
+            // SAFETY: addr came from shmat above
+            unsafe {
+                libc::shmdt(addr);
+            }
+            return Err(xcb::Error::from(e).into());
+        }
+
+        Ok(Self {
+            conn,
+            root,
+            w,
+            h,
+            seg,
+            addr,
+        })
+    }
+}
+
+#[cfg(feature = "shm")]
+impl CaptureBackend for Shm {
+    fn name(&self) -> &'static str {
+        "shm"
+    }
+
+    fn capture(&mut self, timeout: Duration) -> Result<Screenshot<'_>, Error> {
+        let cookie = self.conn.send_request(&xcb::shm::GetImage {
+            drawable: x::Drawable::Window(self.root),
+            x: 0,
+            y: 0,
+            width: self.w,
+            height: self.h,
+            plane_mask: u32::MAX,
+            format: x::ImageFormat::ZPixmap as u8,
+            shmseg: self.seg,
+            offset: 0,
+        });
+        reply_within(&self.conn, cookie, timeout)?;
+
+        let (w, h) = (usize::from(self.w), usize::from(self.h));
+        // SAFETY: the segment is w * h * 4 bytes, attached until self is dropped,
+        // and the X server is done writing once it has replied
+        let buf = unsafe { std::slice::from_raw_parts_mut(self.addr.cast::<BGRA8>(), w * h) };
+        Ok(Screenshot::new(buf, w, h))
+    }
+}
+
+#[cfg(feature = "shm")]
+impl Drop for Shm {
+    fn drop(&mut self) {
+        // don't wait for the X server, it may be why capture timed out
+        self.conn
+            .send_request(&xcb::shm::Detach { shmseg: self.seg });
+        let _ = self.conn.flush();
+        // SAFETY: addr came from shmat and nothing borrows it past self
+        unsafe {
+            libc::shmdt(self.addr);
+        }
+    }
+}
+
+/// The default X screen with a plain `GetImage` request. Slower, since the
+/// image is copied through the socket, but works on any X server.
+pub struct GetImage {
+    conn: Connection,
+    root: x::Window,
+    w: u16,
+    h: u16,
+    buf: Secret<BGRA8>,
+}
+
+impl GetImage {
+    /// Connect to X.
+    pub fn new() -> Result<Self, Error> {
+        let (conn, screen_num) = Connection::connect(None)?;
+        let (root, w, h) = root(&conn, screen_num)?;
+        Ok(Self {
+            conn,
+            root,
+            w,
+            h,
+            buf: Secret::new(Vec::new()),
+        })
+    }
+}
+
+impl CaptureBackend for GetImage {
+    fn name(&self) -> &'static str {
+        "get-image"
+    }
+
+    fn capture(&mut self, timeout: Duration) -> Result<Screenshot<'_>, Error> {
+        let cookie = self.conn.send_request(&x::GetImage {
+            format: x::ImageFormat::ZPixmap,
+            drawable: x::Drawable::Window(self.root),
+            x: 0,
+            y: 0,
+            width: self.w,
+            height: self.h,
+            plane_mask: u32::MAX,
+        });
+        let reply = reply_within(&self.conn, cookie, timeout)?;
+
+        let (w, h) = (usize::from(self.w), usize::from(self.h));
+        let data = reply.data();
+        let (ptr, len) = (data.as_ptr().cast_mut(), data.len());
+        let complete = len / 4 >= w * h;
+        if complete {
+            // straight into memory that's locked and wiped, rather than through a plain copy
+            self.buf = Secret::new(vec![BGRA8::default(); w * h]);
+            self.buf.copy_from_slice(&data.as_bgra()[..w * h]);
+        }
+
+        // xcb frees the reply without wiping it
+        // SAFETY: the data lives in the reply's malloc'd buffer, which is writable and
+        // isn't borrowed anymore, so nothing sees it while it's wiped
+        secret::wipe(unsafe { std::slice::from_raw_parts_mut(ptr, len) });
+        drop(reply);
+
+        if !complete {
+            return Err(CaptureError::Frame(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("GetImage returned {} pixels, expected {w}x{h}", len / 4),
+            ))
+            .into());
+        }
+        Ok(Screenshot::new(&mut *self.buf, w, h))
+    }
+}
+
+/// An image read with [`input::read`](crate::input::read) instead of the screen.
+pub struct File(ImgVec<BGRA8>);
+
+impl File {
+    /// Lock with `img`.
+    pub const fn new(img: ImgVec<BGRA8>) -> Self {
+        Self(img)
+    }
+}
+
+impl CaptureBackend for File {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn capture(&mut self, _timeout: Duration) -> Result<Screenshot<'_>, Error> {
+        let (w, h) = (self.0.width(), self.0.height());
+        Ok(Screenshot::new(self.0.buf_mut().as_mut_slice(), w, h))
+    }
+}
+
+/// What to lock with when the screen can't be captured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fallback {
+    /// A solid color the size of the screen
+    Color(BGRA8),
+    /// The image last saved here with [`save_cache`]
+    Cached(PathBuf),
+}
+
+impl Fallback {
+    /// The image to lock with instead, `screen_size` giving the size for a solid color.
+    pub fn image<F>(&self, screen_size: F) -> Result<ImgVec<BGRA8>, Error>
+    where
+        F: FnOnce() -> Result<(usize, usize), Error>,
+    {
+        match self {
+            Self::Color(color) => {
+                let (w, h) = screen_size()?;
+                Ok(ImgVec::new(vec![*color; w * h], w, h))
+            }
+            Self::Cached(path) => read_cache(path),
+        }
+    }
+}
+
+/// Save `img` for [`Fallback::Cached`], readable only by the current user.
+///
+/// It's stored as is, so `path` should be somewhere that doesn't outlive the session,
+/// like [`runtime_dir`](crate::runtime_dir).
+pub fn save_cache(path: &Path, img: ImgRef<BGRA8>) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+    }
+    let (w, h) = (img.width(), img.height());
+    let (Ok(w32), Ok(h32)) = (u32::try_from(w), u32::try_from(h)) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "image too large to cache").into());
+    };
+
+    // write then rename, so a failed write never leaves a broken cache behind
+    let tmp = path.with_extension("tmp");
+    let mut file = secret::Buffered::new(
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)?,
+    );
+    file.write_all(&w32.to_le_bytes())?;
+    file.write_all(&h32.to_le_bytes())?;
+    for row in img.rows() {
+        file.write_all(row.as_bytes())?;
+    }
+    file.flush()?;
+    drop(file);
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// A width and height, each little-endian `u32`, then BGRA pixels.
+fn read_cache(path: &Path) -> Result<ImgVec<BGRA8>, Error> {
+    let mut file = FsFile::open(path)?;
+    let mut header = [0; 8];
+    file.read_exact(&mut header)?;
+    let [w0, w1, w2, w3, h0, h1, h2, h3] = header;
+    let w = u32::from_le_bytes([w0, w1, w2, w3]) as usize;
+    let h = u32::from_le_bytes([h0, h1, h2, h3]) as usize;
+
+    // check the size against the file before trusting it with an allocation
+    let len = file.metadata()?.len().saturating_sub(8);
+    let size = w.checked_mul(h).and_then(|px| px.checked_mul(4));
+    if size.and_then(|size| u64::try_from(size).ok()) != Some(len) {
+        return Err(CaptureError::Cache(format!(
+            "{}: {len} bytes of pixels, not a {w}x{h} image",
+            path.display()
+        ))
+        .into());
+    }
+
+    let mut bytes = Secret::new(vec![0; usize::try_from(len).unwrap_or_default()]);
+    file.read_exact(&mut bytes)?;
+    Ok(ImgVec::new(bytes.as_bgra().to_vec(), w, h))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache() {
+        let path = std::env::temp_dir().join(format!("i3lockr-{}/last.bgra", std::process::id()));
+        let px = |v| BGRA8 {
+            b: v,
+            g: v,
+            r: v,
+            a: 255,
+        };
+        // a view with padding, which isn't saved
+        let buf = [px(1), px(2), px(0), px(3), px(4), px(0)];
+        save_cache(&path, ImgRef::new_stride(&buf, 2, 2, 3)).unwrap();
+
+        let img = Fallback::Cached(path.clone())
+            .image(|| unreachable!())
+            .unwrap();
+        assert_eq!((img.width(), img.height()), (2, 2));
+        assert_eq!(img.buf(), &[px(1), px(2), px(3), px(4)]);
+
+        let img = Fallback::Color(px(9)).image(|| Ok((3, 1))).unwrap();
+        assert_eq!(img.buf(), &[px(9); 3]);
+
+        // a header that doesn't match the pixels, or overflows
+        for (w, h) in [(3, 2), (u32::MAX, u32::MAX)] {
+            let mut bad = w.to_le_bytes().to_vec();
+            bad.extend(h.to_le_bytes());
+            bad.extend([0; 16]);
+            fs::write(&path, bad).unwrap();
+            let e = Fallback::Cached(path.clone()).image(|| unreachable!());
+            assert!(matches!(e, Err(Error::Capture(CaptureError::Cache(_)))));
+        }
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn screenshot_wiped() {
+        let mut buf = vec![BGRA8::new(1, 2, 3, 4); 6];
+        secret::tests::watch();
+        let mut screenshot = Screenshot::new(buf.as_mut_slice(), 3, 2);
+        assert_eq!(screenshot.as_img().buf()[5], BGRA8::new(1, 2, 3, 4));
+        drop(screenshot);
+        assert_eq!(secret::tests::wiped(), Some(24));
+        assert!(buf.iter().all(|px| *px == BGRA8::default()));
+    }
+
+    #[test]
+    fn no_backend() {
+        let e = CaptureError::NoBackend(vec![
+            (
+                "scrap",
+                CaptureError::Timeout(Duration::from_secs(1)).into(),
+            ),
+            (
+                "shm",
+                CaptureError::Unsupported("no MIT-SHM".to_string()).into(),
+            ),
+        ]);
+        assert_eq!(
+            e.to_string(),
+            "no backend works (scrap: no frame within 1s; shm: no MIT-SHM)"
+        );
+    }
+}
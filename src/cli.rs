@@ -3,8 +3,20 @@ use std::path::PathBuf;
 
 use clap::{ArgAction, Parser};
 
+/// Exit statuses, besides 0 once the screen was locked.
+const EXIT_STATUS: &str = "\
+Exit status:
+  1  Anything else went wrong
+  2  Invalid command line
+  3  The locker isn't installed
+  4  The locker can't be used with the arguments passed to it
+  5  The locker exited with an error
+  6  The locker crashed, or was killed
+When every locker in --locker failed, it's the status for the first one installed.";
+
 /// Distort a screenshot and run i3lock
 #[derive(Parser, Debug)]
+#[command(after_help = EXIT_STATUS)]
 pub struct Cli {
     /// Prints version information
     #[arg(short = 'V', long = "version", alias = "vers")]
@@ -15,6 +27,21 @@ pub struct Cli {
     #[arg(short = 'v', long = "verbose", alias = "verb", alias = "debug")]
     pub verbose: bool,
 
+    /// Give up without locking if anything goes wrong, instead of reporting it and
+    /// locking with what's left, a plain color at worst. Useful for debugging a setup.
+    /// Implied by --output.
+    #[arg(long = "strict")]
+    pub strict: bool,
+
+    /// Read settings from this file instead of $XDG_CONFIG_HOME/i3lockr/config.toml.
+    /// Flags given on the command line take precedence over the file.
+    #[arg(long = "config", value_name = "config.toml")]
+    pub config: Option<PathBuf>,
+
+    /// Apply the named [profile.<name>] from the config file on top of its other settings.
+    #[arg(long = "profile", value_name = "name")]
+    pub profile: Option<String>,
+
     /// Darken the screenshot by [1, 255]. Example: 15
     #[arg(long = "darken", visible_alias = "dark", conflicts_with = "bright")]
     pub dark: Option<NonZeroU8>,
@@ -31,15 +58,65 @@ pub struct Cli {
     #[arg(short = 'p', long = "scale")]
     pub factor: Option<NonZeroUsize>,
 
-    /// Don't overlay an icon on these monitors. Useful if you're mirroring displays. Must be comma separated.
-    /// Example: 0,2
+    /// Pixelate the screenshot into blocks this many pixels wide,
+    /// each filled with its average color. Example: 16
+    #[arg(long = "pixelate", value_name = "block")]
+    pub block: Option<NonZeroUsize>,
+
+    /// Run these effects in order instead of --scale, --blur, --pixelate, --brighten and --darken.
+    /// Effects may repeat. Must be comma separated. Available: blur:<radius>, brighten:<amt>,
+    /// darken:<amt>, pixelate:<block>, scale-down:<factor>, scale-up:<factor>, invert.
+    /// Example: "scale-down:2,blur:10,scale-up:2,darken:30,invert"
+    #[arg(
+        long = "effects",
+        value_name = "blur:20,darken:30",
+        value_delimiter = ',',
+        conflicts_with_all = ["dark", "bright", "radius", "factor", "block"]
+    )]
+    pub effects: Vec<String>,
+
+    /// Lock with this PNG, JPEG or raw BGRA image instead of a screenshot.
+    /// Raw images are the size of --monitors, or of the X screen.
+    #[arg(long = "input", value_name = "file.png")]
+    pub input: Option<PathBuf>,
+
+    /// Take the screenshot with this backend instead of the first that works,
+    /// trying scrap, shm then get-image. "file" is implied by --input.
+    #[arg(
+        long = "capture-backend",
+        value_name = "backend",
+        value_parser = ["scrap", "shm", "get-image", "file"]
+    )]
+    pub capture_backend: Option<String>,
+
+    /// Give up on capturing the screen after this many milliseconds. Example: 2000
+    #[arg(long = "capture-timeout", value_name = "ms", default_value_t = 2000)]
+    pub capture_timeout: u64,
+
+    /// Lock with this instead if the screen can't be captured: a color as "rrggbb",
+    /// or "cached" for the image from the last lock, saved after effects in $XDG_RUNTIME_DIR,
+    /// and not at all without effects. Without it, a failed capture locks with plain black,
+    /// or is an error with --strict.
+    #[arg(long = "capture-fallback", value_name = "rrggbb|cached", value_parser = parse_fallback)]
+    pub capture_fallback: Option<CaptureFallback>,
+
+    /// Place the icon and text on these monitors instead of asking RandR, as "WxH+X+Y"
+    /// with an optional "name:" prefix. Must be comma separated. Offsets may be negative,
+    /// as in "WxH+-X+Y", in which case the layout is moved to start at the top-left of the image.
+    /// Example: "1920x1080+0+0,DP-1:1280x1024+1920+0"
+    #[arg(long = "monitors", value_name = "WxH+X+Y", value_delimiter = ',')]
+    pub monitors: Vec<String>,
+
+    /// Don't overlay an icon or text on these monitors. Mirrored displays are already only drawn on once. Must be comma separated.
+    /// Each is a RandR output name, "edid:" and the monitor's name, serial number or PNP ID from its EDID,
+    /// or an index in the order monitors are found or given with --monitors. Example: eDP-1,edid:DELL U2415
     #[arg(
         long = "ignore-monitors",
-        value_name = "0,2",
+        value_name = "eDP-1,0",
         value_delimiter = ',',
         visible_alias = "ignore"
     )]
-    pub ignore: Vec<usize>,
+    pub ignore: Vec<String>,
 
     /// Interpret the icon as a mask, inverting masked pixels
     /// on the screenshot. Try it to see an example.
@@ -65,7 +142,93 @@ pub struct Cli {
     #[arg(short = 'i', long = "icon", value_name = "file.png")]
     pub path: Option<PathBuf>,
 
-    /// Arguments to pass to i3lock. Example: "--nofork --ignore-empty-password"
+    /// Text to draw on every monitor. Requires --font. Use "\n" for line breaks.
+    /// Placeholders: {time}, {time:%H:%M:%S}, {date}, {date:%A}, {user}, {realname}, {host},
+    /// {monitor}, {uptime}. Use "{{" and "}}" for literal braces.
+    /// Example: "Locked by {realname} at {time}"
+    #[arg(long = "text", requires = "font")]
+    pub text: Option<String>,
+
+    /// TrueType/OpenType font to draw --text with.
+    #[arg(long = "font", value_name = "file.ttf")]
+    pub font: Option<PathBuf>,
+
+    /// Text height in pixels.
+    #[arg(long = "text-size", value_name = "px", default_value_t = 32.0)]
+    pub text_size: f32,
+
+    /// Text color, "rrggbb" or "rrggbbaa".
+    #[arg(long = "text-color", value_name = "rrggbb", value_parser = parse_color, default_value = "ffffff")]
+    pub text_color: [u8; 4],
+
+    /// Opacity of the text, including its outline and shadow, [0, 255].
+    #[arg(long = "text-alpha", value_name = "alpha", default_value_t = 255)]
+    pub text_alpha: u8,
+
+    /// Outline the text with a border this many pixels wide.
+    #[arg(long = "text-outline", value_name = "px")]
+    pub text_outline: Option<NonZeroUsize>,
+
+    /// Outline color, "rrggbb" or "rrggbbaa".
+    #[arg(long = "text-outline-color", value_name = "rrggbb", value_parser = parse_color, default_value = "000000")]
+    pub text_outline_color: [u8; 4],
+
+    /// Draw a drop shadow offset by "x,y" pixels. Must be comma separated. Example: "2,2"
+    #[arg(
+        long = "text-shadow",
+        allow_hyphen_values = true,
+        value_name = "2,2",
+        num_args = 1,
+        value_delimiter = ',',
+        allow_negative_numbers = true
+    )]
+    pub text_shadow: Vec<isize>,
+
+    /// Shadow color, "rrggbb" or "rrggbbaa".
+    #[arg(long = "text-shadow-color", value_name = "rrggbb", value_parser = parse_color, default_value = "000000a0")]
+    pub text_shadow_color: [u8; 4],
+
+    /// Text placement on each monitor, "x,y" (from top-left), or "-x,-y" (from bottom-right).
+    /// Must be comma separated. Defaults to centered below the icon if not specified.
+    /// Example: "20,-20"
+    #[arg(
+        long = "text-position",
+        allow_hyphen_values = true,
+        value_name = "20,-20",
+        num_args = 1,
+        value_delimiter = ',',
+        allow_negative_numbers = true,
+        visible_alias = "text-pos"
+    )]
+    pub text_pos: Vec<isize>,
+
+    /// Turn the icon and text with each rotated monitor so they're upright on its panel,
+    /// placing the text as if the monitor weren't rotated.
+    #[arg(long = "rotate-with-output")]
+    pub rotate: bool,
+
+    /// Write the processed screenshot to this PNG file instead of locking, or "-" for stdout.
+    /// Useful for tuning effects and placement, or as a login screen background.
+    /// With several X screens each gets its own file, e.g. "lock-1.png" for screen 1.
+    /// Locking covers only the display's default screen, since a locker on another couldn't
+    /// grab the keyboard and pointer the first holds for the whole display.
+    #[arg(short = 'o', long = "output", value_name = "file.png")]
+    pub output: Option<PathBuf>,
+
+    /// Lock with the first of these that works, passing the arguments after "--" to the
+    /// first and any that take the same ones. Swaylock and xsecurelock are given a PNG,
+    /// swaylock one per monitor, and xsecurelock shows it with saver_mpv.
+    /// slock doesn't show the image. Must be comma separated.
+    #[arg(
+        long = "locker",
+        value_name = "locker",
+        value_parser = ["i3lock", "i3lock-color", "swaylock", "xsecurelock", "slock"],
+        value_delimiter = ',',
+        default_value = "i3lock,i3lock-color,xsecurelock,slock"
+    )]
+    pub locker: Vec<String>,
+
+    /// Arguments to pass to the locker. Example: "--nofork --ignore-empty-password"
     #[arg(
         value_name = "i3lock",
         action = ArgAction::Append,
@@ -73,3 +236,37 @@ pub struct Cli {
     )]
     pub i3lock: Vec<String>,
 }
+
+/// What to lock with when capture fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureFallback {
+    Color([u8; 4]),
+    Cached,
+}
+
+/// Parse "cached" or a color for --capture-fallback.
+pub fn parse_fallback(s: &str) -> Result<CaptureFallback, String> {
+    if s == "cached" {
+        Ok(CaptureFallback::Cached)
+    } else {
+        parse_color(s)
+            .map(CaptureFallback::Color)
+            .map_err(|e| format!("{e}, or \"cached\""))
+    }
+}
+
+/// Parse "rrggbb" or "rrggbbaa", with an optional leading '#', into RGBA.
+pub fn parse_color(s: &str) -> Result<[u8; 4], String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!(
+            "\"{s}\" is not a color, expected \"rrggbb\" or \"rrggbbaa\""
+        ));
+    }
+
+    let mut rgba = [255; 4];
+    for (i, c) in rgba.iter_mut().enumerate().take(hex.len() / 2) {
+        *c = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|e| e.to_string())?;
+    }
+    Ok(rgba)
+}
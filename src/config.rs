@@ -0,0 +1,553 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind::NotFound;
+use std::num::{NonZeroU8, NonZeroUsize};
+use std::path::PathBuf;
+
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use serde::{de, Deserialize, Deserializer};
+
+use i3lockr::capture;
+use i3lockr::lock;
+use i3lockr::monitor::Selector;
+use i3lockr::pipeline::Pipeline;
+use i3lockr::Error;
+
+use crate::cli::{parse_color, parse_fallback, CaptureFallback, Cli};
+
+/// Settings read from a TOML file. Keys are named after the long flags.
+///
+/// Named profiles live in `[profile.<name>]` tables with the same keys,
+/// plus `inherits` to build on another profile.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub inherits: Option<String>,
+    #[serde(default)]
+    pub profile: BTreeMap<String, Config>,
+    pub verbose: Option<bool>,
+    pub strict: Option<bool>,
+    pub darken: Option<NonZeroU8>,
+    pub brighten: Option<NonZeroU8>,
+    pub blur: Option<NonZeroUsize>,
+    pub scale: Option<NonZeroUsize>,
+    pub pixelate: Option<NonZeroUsize>,
+    pub effects: Option<Vec<String>>,
+    pub input: Option<PathBuf>,
+    pub capture_backend: Option<String>,
+    pub capture_timeout: Option<u64>,
+    #[serde(default, deserialize_with = "fallback")]
+    pub capture_fallback: Option<CaptureFallback>,
+    pub monitors: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "selectors")]
+    pub ignore_monitors: Option<Vec<String>>,
+    pub invert: Option<bool>,
+    pub position: Option<[isize; 2]>,
+    pub icon: Option<PathBuf>,
+    pub text: Option<String>,
+    pub font: Option<PathBuf>,
+    pub text_size: Option<f32>,
+    #[serde(default, deserialize_with = "color")]
+    pub text_color: Option<[u8; 4]>,
+    pub text_alpha: Option<u8>,
+    pub text_outline: Option<NonZeroUsize>,
+    #[serde(default, deserialize_with = "color")]
+    pub text_outline_color: Option<[u8; 4]>,
+    pub text_shadow: Option<[isize; 2]>,
+    #[serde(default, deserialize_with = "color")]
+    pub text_shadow_color: Option<[u8; 4]>,
+    pub text_position: Option<[isize; 2]>,
+    pub rotate_with_output: Option<bool>,
+    pub locker: Option<Vec<String>>,
+    pub i3lock: Option<Vec<String>>,
+}
+
+fn fallback<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<CaptureFallback>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_fallback(&s).map(Some).map_err(de::Error::custom)
+}
+
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<[u8; 4]>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_color(&s).map(Some).map_err(de::Error::custom)
+}
+
+/// Monitors by name, or by index as before names were allowed.
+fn selectors<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum IndexOrName {
+        Index(usize),
+        Name(String),
+    }
+
+    let selectors = Vec::<IndexOrName>::deserialize(deserializer)?;
+    Ok(Some(
+        selectors
+            .into_iter()
+            .map(|sel| match sel {
+                IndexOrName::Index(i) => i.to_string(),
+                IndexOrName::Name(name) => name,
+            })
+            .collect(),
+    ))
+}
+
+impl Config {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let config: Self = toml::from_str(s).map_err(|e| e.to_string())?;
+        if config.inherits.is_some() {
+            return Err("key `inherits` is only allowed in a profile".into());
+        }
+        config.validate("top level")?;
+        for (name, profile) in &config.profile {
+            if !profile.profile.is_empty() {
+                return Err(format!("profile `{name}`: profiles cannot be nested"));
+            }
+            profile.validate(&format!("profile `{name}`"))?;
+        }
+        Ok(config)
+    }
+
+    fn validate(&self, table: &str) -> Result<(), String> {
+        if self.darken.is_some() && self.brighten.is_some() {
+            return Err(format!(
+                "{table}: keys `darken` and `brighten` cannot be used together"
+            ));
+        }
+        if let Some(ref effects) = self.effects {
+            Pipeline::parse(effects).map_err(|e| format!("{table}: key `effects`: {e}"))?;
+        }
+        if let Some(ref backend) = self.capture_backend {
+            if backend != "file" && !capture::BACKENDS.contains(&backend.as_str()) {
+                return Err(format!(
+                    "{table}: key `capture-backend`: unknown backend \"{backend}\", expected one of: {}, file",
+                    capture::BACKENDS.join(", ")
+                ));
+            }
+        }
+        for sel in self.ignore_monitors.iter().flatten() {
+            sel.parse::<Selector>()
+                .map_err(|e| format!("{table}: key `ignore-monitors`: {e}"))?;
+        }
+        if self.locker.as_ref().is_some_and(Vec::is_empty) {
+            return Err(format!("{table}: key `locker` needs at least one locker"));
+        }
+        for locker in self.locker.iter().flatten() {
+            lock::open(locker).map_err(|e| format!("{table}: key `locker`: {e}"))?;
+        }
+        if self.effects.is_some() && self.has_effect_keys() {
+            return Err(format!(
+                "{table}: key `effects` cannot be used with `scale`, `blur`, `pixelate`, `brighten` or `darken`"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Merge the named profile, and every profile it inherits from, over the top-level keys.
+    pub fn resolve(mut self, name: Option<&str>) -> Result<Self, String> {
+        let mut profiles = std::mem::take(&mut self.profile);
+        let available = profiles.keys().cloned().collect::<Vec<_>>().join(", ");
+
+        let mut chain: Vec<(String, Self)> = Vec::new();
+        let mut next = name.map(str::to_owned);
+        while let Some(name) = next {
+            let Some(profile) = profiles.remove(&name) else {
+                if let Some(start) = chain.iter().position(|(n, _)| *n == name) {
+                    let cycle: Vec<_> = chain[start..]
+                        .iter()
+                        .map(|(n, _)| format!("`{n}`"))
+                        .chain([format!("`{name}`")])
+                        .collect();
+                    return Err(format!(
+                        "profiles inherit in a cycle: {}",
+                        cycle.join(" -> ")
+                    ));
+                }
+                return Err(format!("no profile `{name}`, available: [{available}]"));
+            };
+            next = profile.inherits.clone();
+            chain.push((name, profile));
+        }
+
+        Ok(chain
+            .into_iter()
+            .rev()
+            .fold(self, |base, (_, profile)| base.overlay(profile)))
+    }
+
+    fn has_effect_keys(&self) -> bool {
+        self.scale.is_some()
+            || self.blur.is_some()
+            || self.pixelate.is_some()
+            || self.brighten.is_some()
+            || self.darken.is_some()
+    }
+
+    fn overlay(mut self, top: Self) -> Self {
+        // darken and brighten are exclusive, so setting either replaces both
+        if top.darken.is_some() || top.brighten.is_some() {
+            self.darken = top.darken;
+            self.brighten = top.brighten;
+        }
+
+        // likewise effects replaces the individual effect keys, and vice versa
+        if top.effects.is_some() {
+            self.scale = None;
+            self.blur = None;
+            self.pixelate = None;
+            self.brighten = None;
+            self.darken = None;
+        } else if top.has_effect_keys() {
+            self.effects = None;
+        }
+        self.effects = top.effects.or(self.effects);
+
+        self.verbose = top.verbose.or(self.verbose);
+        self.strict = top.strict.or(self.strict);
+        self.blur = top.blur.or(self.blur);
+        self.scale = top.scale.or(self.scale);
+        self.pixelate = top.pixelate.or(self.pixelate);
+        self.input = top.input.or(self.input);
+        self.capture_backend = top.capture_backend.or(self.capture_backend);
+        self.capture_timeout = top.capture_timeout.or(self.capture_timeout);
+        self.capture_fallback = top.capture_fallback.or(self.capture_fallback);
+        self.monitors = top.monitors.or(self.monitors);
+        self.ignore_monitors = top.ignore_monitors.or(self.ignore_monitors);
+        self.invert = top.invert.or(self.invert);
+        self.position = top.position.or(self.position);
+        self.icon = top.icon.or(self.icon);
+        self.text = top.text.or(self.text);
+        self.font = top.font.or(self.font);
+        self.text_size = top.text_size.or(self.text_size);
+        self.text_color = top.text_color.or(self.text_color);
+        self.text_alpha = top.text_alpha.or(self.text_alpha);
+        self.text_outline = top.text_outline.or(self.text_outline);
+        self.text_outline_color = top.text_outline_color.or(self.text_outline_color);
+        self.text_shadow = top.text_shadow.or(self.text_shadow);
+        self.text_shadow_color = top.text_shadow_color.or(self.text_shadow_color);
+        self.text_position = top.text_position.or(self.text_position);
+        self.rotate_with_output = top.rotate_with_output.or(self.rotate_with_output);
+        self.locker = top.locker.or(self.locker);
+        self.i3lock = top.i3lock.or(self.i3lock);
+        self
+    }
+}
+
+/// `$XDG_CONFIG_HOME/i3lockr`, falling back to `~/.config/i3lockr`.
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join(env!("CARGO_PKG_NAME")))
+}
+
+impl Cli {
+    /// Fill in anything not given on the command line from the config file,
+    /// with the profile selected by `--profile` applied on top.
+    ///
+    /// A missing file is only an error if it was asked for with `--config` or `--profile`.
+    pub fn load_config(&mut self, matches: &ArgMatches) -> Result<(), Error> {
+        let (path, explicit) = match self.config {
+            Some(ref path) => (path.clone(), true),
+            None => match config_dir() {
+                Some(dir) => (dir.join("config.toml"), false),
+                None => return Ok(()),
+            },
+        };
+
+        let s = match fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == NotFound && !explicit && self.profile.is_none() => return Ok(()),
+            Err(e) => {
+                return Err(Error::Config(format!(
+                    "Failed to read {}: {e}",
+                    path.display()
+                )))
+            }
+        };
+        let config = Config::parse(&s)
+            .and_then(|config| config.resolve(self.profile.as_deref()))
+            .map_err(|e| Error::Config(format!("{}: {e}", path.display())))?;
+        self.layer(config, matches)
+            .map_err(|e| Error::Config(format!("{}: {e}", path.display())))?;
+        Ok(())
+    }
+
+    pub fn layer(&mut self, config: Config, matches: &ArgMatches) -> Result<(), String> {
+        // clap only makes --text require --font on the command line
+        if (self.text.is_some() || config.text.is_some())
+            && self.font.is_none()
+            && config.font.is_none()
+        {
+            return Err("key `text` needs `font`".to_string());
+        }
+
+        let unset = |id| matches.value_source(id) != Some(ValueSource::CommandLine);
+
+        // --effects and the individual effect flags replace each other's keys
+        let effect_flags = ["dark", "bright", "radius", "factor", "block"];
+        if unset("effects") && effect_flags.into_iter().all(unset) {
+            layer(&mut self.effects, config.effects);
+        }
+        let effects_unset = unset("effects") && self.effects.is_empty();
+
+        // darken and brighten are exclusive, so a flag for either overrides both keys
+        if effects_unset && unset("dark") && unset("bright") {
+            layer(&mut self.dark, config.darken.map(Some));
+            layer(&mut self.bright, config.brighten.map(Some));
+        }
+
+        if unset("verbose") {
+            layer(&mut self.verbose, config.verbose);
+        }
+        if unset("strict") {
+            layer(&mut self.strict, config.strict);
+        }
+        if effects_unset && unset("radius") {
+            layer(&mut self.radius, config.blur.map(Some));
+        }
+        if effects_unset && unset("factor") {
+            layer(&mut self.factor, config.scale.map(Some));
+        }
+        if effects_unset && unset("block") {
+            layer(&mut self.block, config.pixelate.map(Some));
+        }
+        if unset("input") {
+            layer(&mut self.input, config.input.map(Some));
+        }
+        if unset("capture_backend") {
+            layer(&mut self.capture_backend, config.capture_backend.map(Some));
+        }
+        if unset("capture_timeout") {
+            layer(&mut self.capture_timeout, config.capture_timeout);
+        }
+        if unset("capture_fallback") {
+            layer(
+                &mut self.capture_fallback,
+                config.capture_fallback.map(Some),
+            );
+        }
+        if unset("monitors") {
+            layer(&mut self.monitors, config.monitors);
+        }
+        if unset("ignore") {
+            layer(&mut self.ignore, config.ignore_monitors);
+        }
+        if unset("invert") {
+            layer(&mut self.invert, config.invert);
+        }
+        if unset("pos") {
+            layer(&mut self.pos, config.position.map(Vec::from));
+        }
+        if unset("path") {
+            layer(&mut self.path, config.icon.map(Some));
+        }
+        if unset("text") {
+            layer(&mut self.text, config.text.map(Some));
+        }
+        if unset("font") {
+            layer(&mut self.font, config.font.map(Some));
+        }
+        if unset("text_size") {
+            layer(&mut self.text_size, config.text_size);
+        }
+        if unset("text_color") {
+            layer(&mut self.text_color, config.text_color);
+        }
+        if unset("text_alpha") {
+            layer(&mut self.text_alpha, config.text_alpha);
+        }
+        if unset("text_outline") {
+            layer(&mut self.text_outline, config.text_outline.map(Some));
+        }
+        if unset("text_outline_color") {
+            layer(&mut self.text_outline_color, config.text_outline_color);
+        }
+        if unset("text_shadow") {
+            layer(&mut self.text_shadow, config.text_shadow.map(Vec::from));
+        }
+        if unset("text_shadow_color") {
+            layer(&mut self.text_shadow_color, config.text_shadow_color);
+        }
+        if unset("text_pos") {
+            layer(&mut self.text_pos, config.text_position.map(Vec::from));
+        }
+        if unset("rotate") {
+            layer(&mut self.rotate, config.rotate_with_output);
+        }
+        if unset("locker") {
+            layer(&mut self.locker, config.locker);
+        }
+        if unset("i3lock") {
+            layer(&mut self.i3lock, config.i3lock);
+        }
+        Ok(())
+    }
+}
+
+fn layer<T>(field: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *field = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::CommandFactory;
+    use clap::FromArgMatches;
+
+    const CONFIG: &str = r#"
+        blur = 25
+        darken = 40
+        icon = "/usr/share/i3lockr/lock.png"
+        position = [945, -20]
+        ignore-monitors = [1, "HDMI-A-0"]
+        i3lock = ["--nofork", "--ignore-empty-password"]
+    "#;
+
+    fn cli(argv: &[&str], config: &str) -> Cli {
+        let matches = Cli::command().get_matches_from(argv);
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        cli.layer(Config::parse(config).unwrap(), &matches).unwrap();
+        cli
+    }
+
+    #[test]
+    fn config_only() {
+        let cli = cli(&["i3lockr"], CONFIG);
+        assert_eq!(cli.radius, NonZeroUsize::new(25));
+        assert_eq!(cli.dark, NonZeroU8::new(40));
+        assert_eq!(cli.pos, [945, -20]);
+        assert_eq!(cli.ignore, ["1", "HDMI-A-0"]);
+        assert_eq!(cli.i3lock, ["--nofork", "--ignore-empty-password"]);
+    }
+
+    #[test]
+    fn text_needs_font() {
+        let matches = Cli::command().get_matches_from(["i3lockr"]);
+        let mut args = Cli::from_arg_matches(&matches).unwrap();
+        let config = Config::parse("text = \"{time}\"").unwrap();
+        assert!(args.layer(config, &matches).is_err());
+
+        let args = cli(&["i3lockr", "--font", "a.ttf"], "text = \"{time}\"");
+        assert_eq!(args.text.as_deref(), Some("{time}"));
+    }
+
+    #[test]
+    fn cli_overrides_config() {
+        let cli = cli(
+            &["i3lockr", "--blur", "5", "--bright", "10", "--", "-n"],
+            CONFIG,
+        );
+        assert_eq!(cli.radius, NonZeroUsize::new(5));
+        assert_eq!(cli.bright, NonZeroU8::new(10));
+        assert_eq!(cli.dark, None);
+        assert_eq!(cli.i3lock, ["-n"]);
+    }
+
+    #[test]
+    fn effects_replace_flags() {
+        let effects = cli(&["i3lockr", "--effects", "pixelate:8,invert"], CONFIG);
+        assert_eq!(effects.radius, None);
+        assert_eq!(effects.dark, None);
+
+        let config = "effects = [\"blur:5\", \"invert\"]";
+        let flags = cli(&["i3lockr", "--darken", "10"], config);
+        assert!(flags.effects.is_empty());
+        assert_eq!(flags.dark, NonZeroU8::new(10));
+
+        assert!(Config::parse("blur = 5\neffects = [\"invert\"]").is_err());
+    }
+
+    #[test]
+    fn unknown_key() {
+        let e = Config::parse("blurr = 25").unwrap_err().to_string();
+        assert!(e.contains("blurr"), "{e}");
+    }
+
+    #[test]
+    fn bad_value() {
+        let e = Config::parse("blur = 0").unwrap_err().to_string();
+        assert!(e.contains("blur = 0"), "{e}");
+    }
+
+    #[test]
+    fn colors() {
+        let config = Config::parse("text-color = \"#ff8000\"").unwrap();
+        assert_eq!(config.text_color, Some([255, 128, 0, 255]));
+        let e = Config::parse("text-color = \"orange\"")
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("text-color"), "{e}");
+
+        let e = Config::parse("capture-backend = \"x11\"").unwrap_err();
+        assert!(e.contains("capture-backend"), "{e}");
+        assert!(Config::parse("capture-backend = \"get-image\"").is_ok());
+    }
+
+    #[test]
+    fn exclusive_keys() {
+        assert!(Config::parse("darken = 1\nbrighten = 1").is_err());
+        assert!(Config::parse("[profile.a]\ndarken = 1\nbrighten = 1").is_err());
+    }
+
+    const PROFILES: &str = r#"
+        blur = 10
+
+        [profile.work]
+        blur = 25
+        darken = 40
+        icon = "/usr/share/company/lock.png"
+
+        [profile.presentation]
+        inherits = "work"
+        brighten = 20
+
+        [profile.home]
+        scale = 8
+        invert = true
+
+        [profile.loop]
+        inherits = "loop"
+    "#;
+
+    fn profile(name: &str) -> Result<Config, String> {
+        Config::parse(PROFILES).unwrap().resolve(Some(name))
+    }
+
+    #[test]
+    fn profile_inherits() {
+        let config = profile("presentation").unwrap();
+        assert_eq!(config.blur, NonZeroUsize::new(25));
+        assert_eq!(config.brighten, NonZeroU8::new(20));
+        assert_eq!(config.darken, None);
+        assert_eq!(config.icon, Some("/usr/share/company/lock.png".into()));
+
+        let config = profile("home").unwrap();
+        assert_eq!(config.blur, NonZeroUsize::new(10));
+        assert_eq!(config.scale, NonZeroUsize::new(8));
+        assert_eq!(config.invert, Some(true));
+    }
+
+    #[test]
+    fn profile_errors() {
+        let e = profile("play").unwrap_err().to_string();
+        assert!(e.contains("home, loop, presentation, work"), "{e}");
+        assert!(profile("loop").is_err());
+
+        let cycle = "[profile.a]\ninherits = \"b\"\n[profile.b]\ninherits = \"a\"";
+        let e = Config::parse(cycle)
+            .unwrap()
+            .resolve(Some("a"))
+            .unwrap_err();
+        assert!(e.contains("`a` -> `b` -> `a`"), "{e}");
+    }
+}
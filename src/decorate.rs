@@ -0,0 +1,314 @@
+//! Drawing the icon and text on each monitor of the screenshot.
+
+#[cfg(any(feature = "png", feature = "jpeg"))]
+use std::path::Path;
+
+#[cfg(feature = "text")]
+use imgref::Img;
+use imgref::{ImgExt, ImgRef, ImgRefMut};
+
+use rgb::alt::BGRA8;
+#[cfg(any(feature = "png", feature = "jpeg"))]
+use rgb::FromSlice;
+
+use crate::monitor::{Geometry, Monitor, Rotation};
+use crate::overlay::{self, Compose};
+use crate::secret::Secret;
+#[cfg(feature = "text")]
+use crate::template::Template;
+#[cfg(feature = "text")]
+use crate::text::{self, Text};
+use crate::Error;
+
+/// Gap between a centered icon and the text below it.
+#[cfg(feature = "text")]
+const TEXT_MARGIN: usize = 16;
+
+/// An image drawn on each monitor, or a mask to invert the screenshot through.
+#[cfg(any(feature = "png", feature = "jpeg"))]
+pub struct Icon {
+    buf: Secret<u8>,
+    width: usize,
+    height: usize,
+    /// Where its top-left corner goes on each monitor, counted from the right or bottom
+    /// edge when negative, or `None` to center it
+    pub pos: Option<(isize, isize)>,
+}
+
+#[cfg(any(feature = "png", feature = "jpeg"))]
+impl Icon {
+    /// Decode the PNG or JPEG at `path`.
+    pub fn open(path: &Path, pos: Option<(isize, isize)>) -> Result<Self, Error> {
+        let image = imagefmt::read(path, imagefmt::ColFmt::BGRA)?;
+        Ok(Self {
+            buf: Secret::new(image.buf),
+            width: image.w,
+            height: image.h,
+            pos,
+        })
+    }
+
+    fn image(&self) -> ImgRef<'_, BGRA8> {
+        ImgRef::new(self.buf.as_bgra(), self.width, self.height)
+    }
+}
+
+/// Text drawn on each monitor, expanded for each from a [`Template`].
+#[cfg(feature = "text")]
+pub struct Label {
+    /// What to write
+    pub template: Template,
+    /// The font and style to write it in
+    pub text: Text,
+    /// Where it goes on each monitor, counted from the right or bottom edge when
+    /// negative, or `None` to center it below a centered icon
+    pub pos: Option<(isize, isize)>,
+}
+
+/// The icon and text to draw on each monitor.
+#[derive(Default)]
+pub struct Decoration {
+    /// The icon, if any
+    #[cfg(any(feature = "png", feature = "jpeg"))]
+    pub icon: Option<Icon>,
+    /// The text, if any
+    #[cfg(feature = "text")]
+    pub label: Option<Label>,
+    /// Invert the screenshot through the icon instead of drawing it, or all of it without one
+    pub invert: bool,
+    /// Turn the icon and text with each rotated monitor so they're upright on its panel,
+    /// placing the text as if the monitor weren't rotated
+    pub rotate: bool,
+}
+
+impl Decoration {
+    /// Draw on `img` once for each of `monitors`, which should already be
+    /// [deduplicated](crate::monitor::dedup).
+    ///
+    /// What was drawn before an error is kept.
+    pub fn draw(&self, img: &mut ImgRefMut<BGRA8>, monitors: &[Monitor]) -> Result<(), Error> {
+        #[cfg(any(feature = "png", feature = "jpeg"))]
+        match self.icon {
+            Some(ref icon) => {
+                for monitor in monitors {
+                    self.draw_icon(img, icon, monitor);
+                }
+            }
+            None if self.invert => img.invert(None, 0, 0),
+            None => {}
+        }
+
+        #[cfg(feature = "text")]
+        if let Some(ref label) = self.label {
+            self.draw_label(img, label, monitors)?;
+        }
+
+        #[cfg(not(feature = "text"))]
+        let _ = monitors;
+        Ok(())
+    }
+
+    #[cfg(any(feature = "png", feature = "jpeg"))]
+    fn draw_icon(&self, img: &mut ImgRefMut<BGRA8>, icon: &Icon, monitor: &Monitor) {
+        let Geometry { x, y, w, h } = monitor.geometry;
+        let image = icon.image();
+
+        // turned around its own center, so it stays where it would be upright
+        let turned = (self.rotate && monitor.rotation != Rotation::Normal)
+            .then(|| overlay::turn(image, monitor.rotation).map_buf(Secret::new));
+        let image = turned.as_ref().map_or(image, |t| t.as_ref());
+
+        if icon.pos.is_none() && (image.width() > w || image.height() > h) {
+            eprintln!("Your image is larger than your monitor, it will be cropped to fit!");
+        }
+        let (x_off, x_skip, iw) = span(x, w, image.width(), icon.pos.map(|(px, _)| px));
+        let (y_off, y_skip, ih) = span(y, h, image.height(), icon.pos.map(|(_, py)| py));
+        let image = image.sub_image(x_skip, y_skip, iw, ih);
+        let Some(image) = clip(img, image, x_off, y_off) else {
+            return;
+        };
+
+        if self.invert {
+            img.invert(Some(image), x_off, y_off);
+        } else {
+            img.compose(image, x_off, y_off);
+        }
+    }
+
+    #[cfg(feature = "text")]
+    fn draw_label(
+        &self,
+        img: &mut ImgRefMut<BGRA8>,
+        label: &Label,
+        monitors: &[Monitor],
+    ) -> Result<(), Error> {
+        let icon_h = self.centered_icon_height();
+
+        // monitors usually share the same text, so only render when it changes
+        let mut rendered: Option<(String, Img<Secret<BGRA8>>)> = None;
+        for &Monitor {
+            ref name,
+            geometry: Geometry { x, y, w, h },
+            rotation,
+            ..
+        } in monitors
+        {
+            let s = label.template.expand(name)?;
+            if rendered.as_ref().is_none_or(|(prev, _)| *prev != s) {
+                let layer = label.text.render(&s).map_buf(Secret::new);
+                rendered = Some((s, layer));
+            }
+            let layer = rendered
+                .as_ref()
+                .unwrap_or_else(|| unreachable!())
+                .1
+                .as_ref();
+
+            // place it on the panel as if it weren't rotated, then turn it with the panel
+            let rotation = if self.rotate {
+                rotation
+            } else {
+                Rotation::Normal
+            };
+            let (w, h) = rotation.unturned(w, h);
+            let layer = layer.sub_image(0, 0, layer.width().min(w), layer.height().min(h));
+            let (lw, lh) = (layer.width(), layer.height());
+            let (x_off, y_off) = match label.pos {
+                Some((tx, ty)) => (text::place(tx, lw, w), text::place(ty, lh, h)),
+                None => (
+                    w.saturating_sub(lw) / 2,
+                    (h / 2 + icon_h / 2 + TEXT_MARGIN).min(h.saturating_sub(lh)),
+                ),
+            };
+            let turned = (rotation != Rotation::Normal)
+                .then(|| overlay::turn(layer, rotation).map_buf(Secret::new));
+            let layer = turned.as_ref().map_or(layer, |t| t.as_ref());
+            let (x_off, y_off) = rotation.place((w, h), (x_off, y_off, lw, lh));
+            let (x_off, y_off) = (x_off + x, y_off + y);
+            if let Some(layer) = clip(img, layer, x_off, y_off) {
+                img.compose(layer, x_off, y_off);
+            }
+        }
+        Ok(())
+    }
+
+    /// Height of the icon when it's centered, so text can go below it.
+    #[cfg(feature = "text")]
+    fn centered_icon_height(&self) -> usize {
+        #[cfg(any(feature = "png", feature = "jpeg"))]
+        if let Some(icon) = self.icon.as_ref().filter(|icon| icon.pos.is_none()) {
+            return icon.height;
+        }
+        0
+    }
+}
+
+/// The part of `layer` that fits on `img` with its top-left corner at (`x`, `y`), so
+/// monitors that run off the image can't take the drawing with them, or `None` if
+/// nothing does.
+fn clip<'a>(
+    img: &ImgRefMut<BGRA8>,
+    layer: ImgRef<'a, BGRA8>,
+    x: usize,
+    y: usize,
+) -> Option<ImgRef<'a, BGRA8>> {
+    let w = layer.width().min(img.width().saturating_sub(x));
+    let h = layer.height().min(img.height().saturating_sub(y));
+    (w > 0 && h > 0).then(|| layer.sub_image(0, 0, w, h))
+}
+
+/// Lay one side of the icon, `size` long, on the side of a monitor that starts at
+/// `start` and is `len` long: centered without a `pos`. Gives where it starts, how
+/// much of the icon to skip, and how much of it then fits on the monitor.
+#[cfg(any(feature = "png", feature = "jpeg"))]
+const fn span(start: usize, len: usize, size: usize, pos: Option<isize>) -> (usize, usize, usize) {
+    let (off, skip) = match pos {
+        // a centered icon too big for the monitor loses its edges, not its middle
+        None => (
+            (len / 2).saturating_sub(size / 2),
+            (size / 2).saturating_sub(len / 2),
+        ),
+        Some(pos) => (wrap_to_screen(pos, len), 0),
+    };
+    let fits = len - off;
+    let rest = size - skip;
+    (start + off, skip, if rest < fits { rest } else { fits })
+}
+
+// credit: @williewillus#8490
+#[cfg(any(feature = "png", feature = "jpeg"))]
+const fn wrap_to_screen(idx: isize, len: usize) -> usize {
+    if len == 0 {
+        return 0;
+    }
+    let pos = idx.unsigned_abs() % len;
+    if pos == 0 {
+        0
+    } else {
+        len - pos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(any(feature = "png", feature = "jpeg"))]
+    fn monitor(x: usize, y: usize, w: usize, h: usize) -> Monitor {
+        Monitor {
+            geometry: Geometry { x, y, w, h },
+            ..Monitor::default()
+        }
+    }
+
+    #[cfg(any(feature = "png", feature = "jpeg"))]
+    #[test]
+    fn spans() {
+        assert_eq!(span(0, 100, 20, None), (40, 0, 20));
+        assert_eq!(span(100, 100, 20, None), (140, 0, 20));
+        // too big: centered by cropping both edges
+        assert_eq!(span(100, 100, 300, None), (100, 100, 100));
+        assert_eq!(span(0, 100, 20, Some(-30)), (70, 0, 20));
+        assert_eq!(span(0, 100, 20, Some(-10)), (90, 0, 10));
+        assert_eq!(span(0, 0, 20, Some(-10)), (0, 0, 0));
+    }
+
+    #[cfg(any(feature = "png", feature = "jpeg"))]
+    #[test]
+    fn icon_larger_than_monitor() {
+        let white = BGRA8 {
+            b: 255,
+            g: 255,
+            r: 255,
+            a: 255,
+        };
+        let icon = |pos| Icon {
+            buf: Secret::new(rgb::ComponentSlice::as_slice(&[white; 12 * 12][..]).to_vec()),
+            width: 12,
+            height: 12,
+            pos,
+        };
+        // the second monitor runs off the image, as a layout that wasn't checked can
+        let monitors = [monitor(0, 0, 4, 4), monitor(4, 2, 4, 4)];
+        for pos in [None, Some((-2, -2)), Some((1, 1))] {
+            let mut buf = vec![BGRA8::default(); 8 * 4];
+            let mut img = ImgRefMut::new(&mut buf, 8, 4);
+            let decoration = Decoration {
+                icon: Some(icon(pos)),
+                ..Decoration::default()
+            };
+            decoration.draw(&mut img, &monitors).unwrap();
+            assert!(img.pixels().any(|px| px == white));
+        }
+
+        let mut buf = vec![BGRA8::default(); 8 * 4];
+        let mut img = ImgRefMut::new(&mut buf, 8, 4);
+        let decoration = Decoration {
+            icon: Some(icon(None)),
+            ..Decoration::default()
+        };
+        decoration.draw(&mut img, &monitors[..1]).unwrap();
+        assert!(img.sub_image(0, 0, 4, 4).pixels().all(|px| px == white));
+        assert!(img.sub_image(4, 0, 4, 4).pixels().all(|px| px != white));
+    }
+}
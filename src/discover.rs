@@ -0,0 +1,503 @@
+//! Finding the monitors on the X screen, with RandR 1.5 monitors, or else RandR CRTCs,
+//! or else Xinerama.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+#[cfg(feature = "xinerama")]
+use xcb::xinerama;
+
+use xcb::{randr, x, Connection, Extension, Xid};
+
+use crate::monitor::{Candidate, Edid, Geometry, Monitor, Rotation, Selector};
+use crate::Error;
+
+/// How the monitors were found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// RandR 1.5 monitors, which also covers those made of several CRTCs or set up with
+    /// `xrandr --setmonitor`
+    Monitors,
+    /// RandR CRTCs, one monitor each
+    Crtcs,
+    /// Xinerama screens
+    Xinerama,
+    /// None of those, so the whole X screen is one monitor
+    Screen,
+}
+
+/// Connect to the X server, with the extensions monitors can be found with if it has them.
+pub fn connect() -> Result<(Connection, i32), Error> {
+    Ok(Connection::connect_with_extensions(
+        None,
+        &[],
+        &[
+            Extension::RandR,
+            #[cfg(feature = "xinerama")]
+            Extension::Xinerama,
+        ],
+    )?)
+}
+
+/// Size of the whole X screen `screen_num`, spanning every monitor.
+pub fn screen_size(conn: &Connection, screen_num: i32) -> (usize, usize) {
+    let screen = screen(conn, screen_num);
+    (
+        usize::from(screen.width_in_pixels()),
+        usize::from(screen.height_in_pixels()),
+    )
+}
+
+/// How many X screens the display has, more than one with Zaphod style multihead.
+pub fn screens(conn: &Connection) -> usize {
+    conn.get_setup().roots().count()
+}
+
+/// `display`, e.g. ":0" or "host:10.0", pointed at X screen `screen` instead of its default.
+pub fn screen_display(display: &str, screen: usize) -> String {
+    let (host, num) = display.rsplit_once(':').unwrap_or(("", display));
+    let num = num.split_once('.').map_or(num, |(num, _)| num);
+    format!("{host}:{num}.{screen}")
+}
+
+/// `path` with `screen` added to its name, e.g. "lock-1.png", or stdout as is.
+pub fn per_screen(path: &Path, screen: &str) -> PathBuf {
+    if path == Path::new("-") {
+        return path.to_path_buf();
+    }
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!("-{screen}"));
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    path.with_file_name(name)
+}
+
+/// The first of the runs writing each X screen that failed, as [`Error::Screen`].
+///
+/// Each run reports its own errors, so only the exit code is kept; one that couldn't be
+/// started or waited for is reported here.
+pub fn first_failure<I>(runs: I) -> Result<(), Error>
+where
+    I: IntoIterator<Item = (usize, io::Result<ExitStatus>)>,
+{
+    let mut result = Ok(());
+    for (screen, run) in runs {
+        let code = match run {
+            Ok(status) if status.success() => continue,
+            Ok(status) => status
+                .code()
+                .and_then(|code| u8::try_from(code).ok())
+                .unwrap_or(1),
+            Err(e) => {
+                eprintln!("Error: Failed to run i3lockr for X screen {screen}: {e}");
+                1
+            }
+        };
+        if result.is_ok() {
+            result = Err(Error::Screen { screen, code });
+        }
+    }
+    result
+}
+
+fn screen(conn: &Connection, screen_num: i32) -> &x::Screen {
+    usize::try_from(screen_num)
+        .ok()
+        .and_then(|n| conn.get_setup().roots().nth(n))
+        .unwrap_or_else(|| unreachable!())
+}
+
+/// Every active monitor on `screen_num` in the order they were found, with their EDID
+/// if `edid` is set, and how they were found.
+///
+/// The first of [`Method`] the server supports that finds any monitor is used.
+pub fn candidates(
+    conn: &Connection,
+    screen_num: i32,
+    edid: bool,
+) -> Result<(Vec<Candidate>, Method), Error> {
+    let root = screen(conn, screen_num).root();
+    let size = screen_size(conn, screen_num);
+    let has = |ext| conn.active_extensions().any(|active| active == ext);
+
+    if has(Extension::RandR) {
+        let cookie = conn.send_request(&randr::QueryVersion {
+            major_version: 1,
+            minor_version: 5,
+        });
+        let version = conn.wait_for_reply(cookie)?;
+        let edid = if edid { atom(conn, b"EDID")? } else { None };
+
+        if (version.major_version(), version.minor_version()) >= (1, 5) {
+            let found = from_monitors(conn, root, size, edid)?;
+            if !found.is_empty() {
+                return Ok((found, Method::Monitors));
+            }
+        }
+        let found = from_crtcs(conn, root, size, edid)?;
+        if !found.is_empty() {
+            return Ok((found, Method::Crtcs));
+        }
+    }
+
+    #[cfg(feature = "xinerama")]
+    if has(Extension::Xinerama) {
+        let found = from_xinerama(conn, size)?;
+        if !found.is_empty() {
+            return Ok((found, Method::Xinerama));
+        }
+    }
+
+    let (w, h) = size;
+    let monitor = Monitor {
+        primary: true,
+        ..Monitor::new("", Geometry { x: 0, y: 0, w, h })
+    };
+    Ok((
+        vec![Candidate {
+            index: 0,
+            monitor,
+            edid: None,
+        }],
+        Method::Screen,
+    ))
+}
+
+/// Every active monitor on `screen_num`, skipping those `ignore` picks.
+pub fn monitors(
+    conn: &Connection,
+    screen_num: i32,
+    ignore: &[Selector],
+) -> Result<Vec<Monitor>, Error> {
+    let edid = ignore.iter().any(|sel| matches!(sel, Selector::Edid(_)));
+    let (mut candidates, _) = candidates(conn, screen_num, edid)?;
+    crate::monitor::skip(&mut candidates, ignore);
+    Ok(candidates.into_iter().map(|c| c.monitor).collect())
+}
+
+/// RandR 1.5 monitors, taking the rotation and EDID from their first output.
+///
+/// They're indexed and ordered by the CRTC driving that output, as [`from_crtcs`] would,
+/// so indexes in `--ignore-monitors` don't depend on how monitors were found.
+fn from_monitors(
+    conn: &Connection,
+    root: x::Window,
+    size: (usize, usize),
+    edid: Option<x::Atom>,
+) -> Result<Vec<Candidate>, Error> {
+    let cookie = conn.send_request(&randr::GetScreenResources { window: root });
+    let crtcs = conn.wait_for_reply(cookie)?.crtcs().to_vec();
+    let cookie = conn.send_request(&randr::GetMonitors {
+        window: root,
+        get_active: true,
+    });
+    let reply = conn.wait_for_reply(cookie)?;
+
+    let mut found = reply
+        .monitors()
+        .enumerate()
+        .map(|(nth, m)| {
+            let cookie = conn.send_request(&x::GetAtomName { atom: m.name() });
+            let name = conn.wait_for_reply(cookie)?.name().to_string();
+            let output = m.outputs().first().copied();
+            let crtc = match output {
+                Some(output) => output_crtc(conn, output)?,
+                None => None,
+            };
+            let rotation = match crtc {
+                Some(crtc) => crtc_rotation(conn, crtc)?,
+                None => Rotation::Normal,
+            };
+            let edid = match (output, edid) {
+                (Some(output), Some(atom)) => output_edid(conn, output, atom)?,
+                _ => None,
+            };
+
+            let Some(geometry) = geometry(m.x(), m.y(), m.width(), m.height(), size) else {
+                return Ok(None);
+            };
+            Ok(Some(Candidate {
+                index: crtc_index(&crtcs, crtc, nth),
+                monitor: Monitor {
+                    primary: m.primary(),
+                    rotation,
+                    physical_mm: physical(m.width_in_millimeters(), m.height_in_millimeters()),
+                    ..Monitor::new(name, geometry)
+                },
+                edid,
+            }))
+        })
+        .filter_map(Result::transpose)
+        .collect::<Result<Vec<_>, Error>>()?;
+    found.sort_by_key(|c| c.index);
+    Ok(found)
+}
+
+/// Where `crtc` is among every CRTC, or for the `nth` monitor, one without a CRTC
+/// after all of them.
+fn crtc_index<T: PartialEq>(crtcs: &[T], crtc: Option<T>, nth: usize) -> usize {
+    crtc.and_then(|crtc| crtcs.iter().position(|c| *c == crtc))
+        .unwrap_or(crtcs.len() + nth)
+}
+
+/// Active RandR CRTCs, named after their first output, indexed among every CRTC.
+fn from_crtcs(
+    conn: &Connection,
+    root: x::Window,
+    size: (usize, usize),
+    edid: Option<x::Atom>,
+) -> Result<Vec<Candidate>, Error> {
+    let cookie = conn.send_request(&randr::GetScreenResources { window: root });
+    let reply = conn.wait_for_reply(cookie)?;
+    let cookie = conn.send_request(&randr::GetOutputPrimary { window: root });
+    let primary = conn.wait_for_reply(cookie)?.output();
+
+    reply
+        .crtcs()
+        .iter()
+        .filter_map(|crtc| {
+            let cookie = conn.send_request(&randr::GetCrtcInfo {
+                crtc: *crtc,
+                config_timestamp: reply.timestamp(),
+            });
+            conn.wait_for_reply(cookie).ok()
+        })
+        .enumerate()
+        .filter(|(_, m)| !m.mode().is_none())
+        .map(|(index, m)| {
+            let output = m.outputs().first().copied();
+            let (name, physical_mm) = match output {
+                Some(output) => {
+                    let cookie = conn.send_request(&randr::GetOutputInfo {
+                        output,
+                        config_timestamp: reply.config_timestamp(),
+                    });
+                    let info = conn.wait_for_reply(cookie)?;
+                    (
+                        String::from_utf8_lossy(info.name()).into_owned(),
+                        physical(info.mm_width(), info.mm_height()),
+                    )
+                }
+                None => (String::new(), None),
+            };
+            let edid = match (output, edid) {
+                (Some(output), Some(atom)) => output_edid(conn, output, atom)?,
+                _ => None,
+            };
+
+            let Some(geometry) = geometry(m.x(), m.y(), m.width(), m.height(), size) else {
+                return Ok(None);
+            };
+            let rotation = rotation(m.rotation());
+            Ok(Some(Candidate {
+                index,
+                monitor: Monitor {
+                    primary: m.outputs().contains(&primary),
+                    rotation,
+                    // outputs give the size of the panel, which the CRTC turns
+                    physical_mm: physical_mm.map(|(w, h)| rotation.unturned(w, h)),
+                    ..Monitor::new(name, geometry)
+                },
+                edid,
+            }))
+        })
+        .filter_map(Result::transpose)
+        .collect()
+}
+
+/// Xinerama screens, which have no names, and the first of which is usually primary.
+#[cfg(feature = "xinerama")]
+fn from_xinerama(conn: &Connection, size: (usize, usize)) -> Result<Vec<Candidate>, Error> {
+    let cookie = conn.send_request(&xinerama::QueryScreens {});
+    let reply = conn.wait_for_reply(cookie)?;
+
+    Ok(reply
+        .screen_info()
+        .iter()
+        .enumerate()
+        .filter_map(|(index, s)| {
+            let geometry = geometry(s.x_org, s.y_org, s.width, s.height, size)?;
+            Some(Candidate {
+                index,
+                monitor: Monitor {
+                    primary: index == 0,
+                    ..Monitor::new("", geometry)
+                },
+                edid: None,
+            })
+        })
+        .collect())
+}
+
+/// The part of a monitor at (`x`, `y`) that's on an X screen `size` big, which is what
+/// gets captured, or `None` if it's entirely off it.
+fn geometry(x: i16, y: i16, w: u16, h: u16, size: (usize, usize)) -> Option<Geometry> {
+    let clip = |pos: i16, len: u16, max: usize| {
+        let start = usize::try_from(pos).unwrap_or(0).min(max);
+        let end = usize::try_from(i32::from(pos) + i32::from(len))
+            .unwrap_or(0)
+            .min(max);
+        (end > start).then_some((start, end - start))
+    };
+    let (x, w) = clip(x, w, size.0)?;
+    let (y, h) = clip(y, h, size.1)?;
+    Some(Geometry { x, y, w, h })
+}
+
+/// Physical size, which is 0x0 when the monitor doesn't say, e.g. for projectors.
+fn physical(w: u32, h: u32) -> Option<(u32, u32)> {
+    (w > 0 && h > 0).then_some((w, h))
+}
+
+fn rotation(rotation: randr::Rotation) -> Rotation {
+    // RandR turns counterclockwise
+    if rotation.contains(randr::Rotation::ROTATE_90) {
+        Rotation::Left
+    } else if rotation.contains(randr::Rotation::ROTATE_180) {
+        Rotation::Inverted
+    } else if rotation.contains(randr::Rotation::ROTATE_270) {
+        Rotation::Right
+    } else {
+        Rotation::Normal
+    }
+}
+
+/// The CRTC driving `output`, if any.
+fn output_crtc(conn: &Connection, output: randr::Output) -> Result<Option<randr::Crtc>, Error> {
+    let cookie = conn.send_request(&randr::GetOutputInfo {
+        output,
+        config_timestamp: x::CURRENT_TIME,
+    });
+    let crtc = conn.wait_for_reply(cookie)?.crtc();
+    Ok((!crtc.is_none()).then_some(crtc))
+}
+
+/// How `crtc` is turned.
+fn crtc_rotation(conn: &Connection, crtc: randr::Crtc) -> Result<Rotation, Error> {
+    let cookie = conn.send_request(&randr::GetCrtcInfo {
+        crtc,
+        config_timestamp: x::CURRENT_TIME,
+    });
+    Ok(rotation(conn.wait_for_reply(cookie)?.rotation()))
+}
+
+/// The atom called `name`, if the X server has one.
+fn atom(conn: &Connection, name: &[u8]) -> Result<Option<x::Atom>, Error> {
+    let cookie = conn.send_request(&x::InternAtom {
+        only_if_exists: true,
+        name,
+    });
+    let atom = conn.wait_for_reply(cookie)?.atom();
+    Ok((atom != x::ATOM_NONE).then_some(atom))
+}
+
+/// The EDID of `output`, if it has one that parses.
+fn output_edid(
+    conn: &Connection,
+    output: randr::Output,
+    atom: x::Atom,
+) -> Result<Option<Edid>, Error> {
+    let cookie = conn.send_request(&randr::GetOutputProperty {
+        output,
+        property: atom,
+        // any type, it's INTEGER in practice
+        r#type: x::ATOM_NONE,
+        long_offset: 0,
+        // in 32 bit units, enough for the base block
+        long_length: 32,
+        delete: false,
+        pending: false,
+    });
+    let reply = conn.wait_for_reply(cookie)?;
+    if reply.format() != 8 {
+        return Ok(None);
+    }
+    Ok(Edid::parse(reply.data::<u8>()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotations() {
+        assert_eq!(rotation(randr::Rotation::ROTATE_0), Rotation::Normal);
+        assert_eq!(rotation(randr::Rotation::ROTATE_90), Rotation::Left);
+        assert_eq!(
+            rotation(randr::Rotation::ROTATE_270 | randr::Rotation::REFLECT_X),
+            Rotation::Right
+        );
+        assert_eq!(physical(0, 0), None);
+
+        // only what's on the screen is captured
+        let screen = (3840, 1080);
+        assert_eq!(
+            geometry(-100, 0, 1920, 1080, screen),
+            Some(Geometry {
+                x: 0,
+                y: 0,
+                w: 1820,
+                h: 1080
+            })
+        );
+        assert_eq!(
+            geometry(2880, 540, 1920, 1080, screen).map(|g| (g.w, g.h)),
+            Some((960, 540))
+        );
+        assert_eq!(geometry(-1920, 0, 1920, 1080, screen), None);
+        assert_eq!(geometry(0, 1080, 1920, 1080, screen), None);
+    }
+
+    #[test]
+    fn crtc_indexes() {
+        // the same as from CRTCs, with monitors that have none last
+        let crtcs = [63, 64, 65];
+        assert_eq!(crtc_index(&crtcs, Some(65), 0), 2);
+        assert_eq!(crtc_index(&crtcs, Some(63), 1), 0);
+        assert_eq!(crtc_index(&crtcs, None, 2), 5);
+        assert_eq!(crtc_index(&crtcs, Some(99), 0), 3);
+    }
+
+    #[test]
+    fn displays() {
+        assert_eq!(screen_display(":0", 1), ":0.1");
+        assert_eq!(screen_display(":1.0", 2), ":1.2");
+        assert_eq!(screen_display("localhost:10.0", 0), "localhost:10.0");
+        assert_eq!(screen_display("unix:0", 1), "unix:0.1");
+    }
+
+    #[test]
+    fn screen_names() {
+        assert_eq!(
+            per_screen(Path::new("/tmp/lock.png"), "1"),
+            Path::new("/tmp/lock-1.png")
+        );
+        assert_eq!(per_screen(Path::new("lock"), "0"), Path::new("lock-0"));
+        assert_eq!(per_screen(Path::new("-"), "1"), Path::new("-"));
+    }
+
+    #[test]
+    fn failed_screens() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let ok = || Ok(ExitStatus::from_raw(0));
+        assert!(first_failure([(0, ok()), (1, ok())]).is_ok());
+
+        // the first failure is kept, a killed run exits with 1
+        let e = first_failure([
+            (0, ok()),
+            (1, Ok(ExitStatus::from_raw(5 << 8))),
+            (2, Ok(ExitStatus::from_raw(9))),
+        ])
+        .unwrap_err();
+        assert!(matches!(e, Error::Screen { screen: 1, code: 5 }), "{e}");
+        assert_eq!(e.exit_code(), 5);
+        let e = first_failure([(0, Ok(ExitStatus::from_raw(9)))]).unwrap_err();
+        assert_eq!(e.exit_code(), 1);
+
+        let e = first_failure([(1, Err(io::ErrorKind::NotFound.into()))]).unwrap_err();
+        assert!(matches!(e, Error::Screen { screen: 1, code: 1 }), "{e}");
+    }
+}
@@ -0,0 +1,267 @@
+//! The [`Effect`] trait and the built-in effects.
+
+use std::fmt;
+use std::num::{NonZeroU8, NonZeroUsize};
+use std::str::FromStr;
+
+use imgref::ImgRefMut;
+
+use rgb::alt::BGRA8;
+
+#[cfg(feature = "blur")]
+use crate::blur::Blur as _;
+#[cfg(feature = "brightness")]
+use crate::brightness::BrightnessAdj as _;
+#[cfg(any(feature = "png", feature = "jpeg", feature = "text"))]
+use crate::overlay::Compose as _;
+#[cfg(feature = "pixelate")]
+use crate::pixelate::Pixelate as _;
+#[cfg(feature = "scale")]
+use crate::scale::Scale as _;
+
+/// A step of the effects pipeline, as named in `--effects`.
+pub trait Effect {
+    /// The name used in `--effects`, e.g. "blur"
+    fn name(&self) -> &'static str;
+
+    /// The argument after the colon in `--effects`, if any
+    fn params(&self) -> Option<String>;
+
+    /// Run the effect on `img`
+    fn apply(&self, img: &mut ImgRefMut<BGRA8>);
+}
+
+impl fmt::Display for dyn Effect + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.params() {
+            Some(params) => write!(f, "{}:{params}", self.name()),
+            None => f.write_str(self.name()),
+        }
+    }
+}
+
+impl fmt::Debug for dyn Effect + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{self}\"")
+    }
+}
+
+/// `scale-down:factor`, which must be followed by a matching [`ScaleUp`].
+///
+/// Only built through [`scaled`] or the [`Registry`], to be run by a
+/// [`Pipeline`](crate::pipeline::Pipeline) that checks it's undone.
+pub(crate) struct ScaleDown(pub(crate) NonZeroUsize);
+/// `scale-up:factor`
+pub(crate) struct ScaleUp(pub(crate) NonZeroUsize);
+/// `blur:radius`
+pub struct Blur(pub NonZeroUsize);
+/// `pixelate:block`
+pub struct Pixelate(pub NonZeroUsize);
+/// `brighten:amount`
+pub struct Brighten(pub NonZeroU8);
+/// `darken:amount`
+pub struct Darken(pub NonZeroU8);
+/// `invert`
+pub struct Invert;
+
+impl Effect for ScaleDown {
+    fn name(&self) -> &'static str {
+        "scale-down"
+    }
+
+    fn params(&self) -> Option<String> {
+        Some(self.0.to_string())
+    }
+
+    fn apply(&self, img: &mut ImgRefMut<BGRA8>) {
+        // the pipeline makes sure this is followed by a matching scale_up
+        #[cfg(feature = "scale")]
+        unsafe {
+            img.scale_down(self.0);
+        }
+        #[cfg(not(feature = "scale"))]
+        crate::warn_disabled!("scale");
+    }
+}
+
+impl Effect for ScaleUp {
+    fn name(&self) -> &'static str {
+        "scale-up"
+    }
+
+    fn params(&self) -> Option<String> {
+        Some(self.0.to_string())
+    }
+
+    fn apply(&self, img: &mut ImgRefMut<BGRA8>) {
+        #[cfg(feature = "scale")]
+        unsafe {
+            img.scale_up(self.0);
+        }
+        #[cfg(not(feature = "scale"))]
+        crate::warn_disabled!("scale");
+    }
+}
+
+impl Effect for Blur {
+    fn name(&self) -> &'static str {
+        "blur"
+    }
+
+    fn params(&self) -> Option<String> {
+        Some(self.0.to_string())
+    }
+
+    fn apply(&self, img: &mut ImgRefMut<BGRA8>) {
+        #[cfg(feature = "blur")]
+        img.blur(self.0);
+        #[cfg(not(feature = "blur"))]
+        crate::warn_disabled!("blur");
+    }
+}
+
+impl Effect for Pixelate {
+    fn name(&self) -> &'static str {
+        "pixelate"
+    }
+
+    fn params(&self) -> Option<String> {
+        Some(self.0.to_string())
+    }
+
+    fn apply(&self, img: &mut ImgRefMut<BGRA8>) {
+        #[cfg(feature = "pixelate")]
+        img.pixelate(self.0);
+        #[cfg(not(feature = "pixelate"))]
+        crate::warn_disabled!("pixelate");
+    }
+}
+
+impl Effect for Brighten {
+    fn name(&self) -> &'static str {
+        "brighten"
+    }
+
+    fn params(&self) -> Option<String> {
+        Some(self.0.to_string())
+    }
+
+    fn apply(&self, img: &mut ImgRefMut<BGRA8>) {
+        #[cfg(feature = "brightness")]
+        img.brighten(self.0);
+        #[cfg(not(feature = "brightness"))]
+        crate::warn_disabled!("brightness");
+    }
+}
+
+impl Effect for Darken {
+    fn name(&self) -> &'static str {
+        "darken"
+    }
+
+    fn params(&self) -> Option<String> {
+        Some(self.0.to_string())
+    }
+
+    fn apply(&self, img: &mut ImgRefMut<BGRA8>) {
+        #[cfg(feature = "brightness")]
+        img.darken(self.0);
+        #[cfg(not(feature = "brightness"))]
+        crate::warn_disabled!("brightness");
+    }
+}
+
+impl Effect for Invert {
+    fn name(&self) -> &'static str {
+        "invert"
+    }
+
+    fn params(&self) -> Option<String> {
+        None
+    }
+
+    fn apply(&self, img: &mut ImgRefMut<BGRA8>) {
+        #[cfg(any(feature = "png", feature = "jpeg", feature = "text"))]
+        img.invert(None, 0, 0);
+        #[cfg(not(any(feature = "png", feature = "jpeg", feature = "text")))]
+        crate::warn_disabled!("invert");
+    }
+}
+
+/// `effects` run on the image scaled down by `factor`, then scaled back up.
+pub fn scaled(factor: NonZeroUsize, effects: Vec<Box<dyn Effect>>) -> Vec<Box<dyn Effect>> {
+    let mut scaled: Vec<Box<dyn Effect>> = vec![Box::new(ScaleDown(factor))];
+    scaled.extend(effects);
+    scaled.push(Box::new(ScaleUp(factor)));
+    scaled
+}
+
+/// Builds an effect from the argument after the colon, if there was one.
+pub type Constructor = fn(Option<&str>) -> Result<Box<dyn Effect>, String>;
+
+/// Effects available to `--effects`, by name.
+#[derive(Default)]
+pub struct Registry(Vec<(&'static str, Constructor)>);
+
+impl Registry {
+    /// Every effect that ships with i3lockr.
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        registry.register("blur", |arg| Ok(Box::new(Blur(param("blur", arg)?))));
+        registry.register("brighten", |arg| {
+            Ok(Box::new(Brighten(param("brighten", arg)?)))
+        });
+        registry.register("darken", |arg| Ok(Box::new(Darken(param("darken", arg)?))));
+        registry.register("pixelate", |arg| {
+            Ok(Box::new(Pixelate(param("pixelate", arg)?)))
+        });
+        registry.register("scale-down", |arg| {
+            Ok(Box::new(ScaleDown(param("scale-down", arg)?)))
+        });
+        registry.register("scale-up", |arg| {
+            Ok(Box::new(ScaleUp(param("scale-up", arg)?)))
+        });
+        registry.register("invert", |arg| match arg {
+            None => Ok(Box::new(Invert)),
+            Some(_) => Err("effect \"invert\" doesn't take an argument".to_string()),
+        });
+        registry
+    }
+
+    /// Add an effect, replacing any other with the same name.
+    pub fn register(&mut self, name: &'static str, constructor: Constructor) {
+        self.0.retain(|(n, _)| *n != name);
+        self.0.push((name, constructor));
+    }
+
+    /// Names of the registered effects, in the order they were added.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.0.iter().map(|(name, _)| *name)
+    }
+
+    /// Build an effect from "name" or "name:argument".
+    ///
+    /// Run it in a [`Pipeline`](crate::pipeline::Pipeline), which checks that a
+    /// "scale-down" is undone, rather than on its own.
+    pub fn parse(&self, s: &str) -> Result<Box<dyn Effect>, String> {
+        let (name, arg) = match s.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s, None),
+        };
+
+        match self.0.iter().find(|(n, _)| *n == name) {
+            Some((_, constructor)) => constructor(arg),
+            None => Err(format!(
+                "unknown effect \"{name}\", expected one of: {}",
+                self.names().collect::<Vec<_>>().join(", ")
+            )),
+        }
+    }
+}
+
+/// Parse the numeric argument of effect `name`.
+pub fn param<T: FromStr>(name: &str, arg: Option<&str>) -> Result<T, String> {
+    arg.ok_or_else(|| format!("effect \"{name}\" needs an argument, e.g. \"{name}:10\""))?
+        .parse()
+        .map_err(|_| format!("invalid argument in effect \"{name}\", expected a positive number"))
+}
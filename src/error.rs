@@ -0,0 +1,119 @@
+use std::{fmt, io};
+
+use crate::capture::CaptureError;
+use crate::lock::LockError;
+
+/// Everything that can go wrong between taking the screenshot and locking.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An invalid `--effects` spec, or effects in an order that can't work
+    Effect(String),
+    /// An unreadable or invalid configuration file
+    Config(String),
+    /// An invalid `--text` template
+    Template(String),
+    /// A font that couldn't be loaded
+    Font(String),
+    /// An image that couldn't be decoded or encoded
+    #[cfg(any(feature = "png", feature = "jpeg"))]
+    Image(imagefmt::Error),
+    /// The image given with `--input` can't be used
+    Input(String),
+    /// The image couldn't be written with `--output`
+    Output(String),
+    /// A monitor that can't be drawn on
+    Monitor(String),
+    /// The screenshot couldn't be taken
+    Capture(CaptureError),
+    /// Talking to the X server failed
+    X(xcb::Error),
+    /// The screen couldn't be locked
+    Lock(LockError),
+    /// The run of i3lockr writing another X screen failed with this exit code, and said why
+    Screen {
+        /// The X screen
+        screen: usize,
+        /// Its exit code, or 1 if it was killed
+        code: u8,
+    },
+    /// Any other I/O error
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Effect(e)
+            | Self::Config(e)
+            | Self::Template(e)
+            | Self::Input(e)
+            | Self::Output(e)
+            | Self::Monitor(e) => f.write_str(e),
+            Self::Font(e) => write!(f, "Failed to load font {e}"),
+            #[cfg(any(feature = "png", feature = "jpeg"))]
+            Self::Image(e) => write!(f, "Failed to read or write image: {e}"),
+            Self::Capture(e) => write!(f, "Failed to capture the screen: {e}"),
+            Self::X(e) => write!(f, "X server error: {e}"),
+            Self::Lock(e) => e.fmt(f),
+            Self::Screen { screen, code } => {
+                write!(f, "writing X screen {screen} failed with exit code {code}")
+            }
+            Self::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(any(feature = "png", feature = "jpeg"))]
+            Self::Image(e) => Some(e),
+            Self::Capture(e) => Some(e),
+            Self::Lock(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::X(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    /// The status i3lockr exits with after this error.
+    ///
+    /// Failures to lock get their own codes, see [`LockError::exit_code`], as do those
+    /// of the run for another X screen, and anything else is 1. An invalid command line
+    /// exits with 2 before getting here.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Lock(e) => e.exit_code(),
+            Self::Screen { code, .. } => *code,
+            _ => 1,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<xcb::Error> for Error {
+    fn from(e: xcb::Error) -> Self {
+        Self::X(e)
+    }
+}
+
+impl From<xcb::ConnError> for Error {
+    fn from(e: xcb::ConnError) -> Self {
+        Self::X(e.into())
+    }
+}
+
+#[cfg(any(feature = "png", feature = "jpeg"))]
+impl From<imagefmt::Error> for Error {
+    fn from(e: imagefmt::Error) -> Self {
+        Self::Image(e)
+    }
+}
@@ -0,0 +1,99 @@
+//! Reading an image to process instead of capturing the screen.
+
+use std::fs;
+use std::path::Path;
+
+use imgref::ImgVec;
+use rgb::alt::BGRA8;
+#[cfg(any(feature = "png", feature = "jpeg"))]
+use rgb::FromSlice;
+
+use crate::secret::Secret;
+use crate::Error;
+
+/// Read the image at `path` in i3lock's native pixel format.
+///
+/// PNG and JPEG files are decoded. Anything else is raw BGRA data, which has no
+/// header, so its size comes from `raw_size`.
+pub fn read<F>(path: &Path, raw_size: F) -> Result<ImgVec<BGRA8>, Error>
+where
+    F: FnOnce() -> Result<(usize, usize), Error>,
+{
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    match ext.as_deref() {
+        Some("png" | "jpg" | "jpeg") => decode(path),
+        _ => {
+            let (w, h) = raw_size()?;
+            read_raw(path, w, h)
+        }
+    }
+}
+
+#[cfg(any(feature = "png", feature = "jpeg"))]
+fn decode(path: &Path) -> Result<ImgVec<BGRA8>, Error> {
+    let mut image = imagefmt::read(path, imagefmt::ColFmt::BGRA)?;
+    let img = ImgVec::new(image.buf.as_bgra().to_vec(), image.w, image.h);
+    crate::secret::wipe(&mut image.buf);
+    Ok(img)
+}
+
+#[cfg(not(any(feature = "png", feature = "jpeg")))]
+fn decode(path: &Path) -> Result<ImgVec<BGRA8>, Error> {
+    Err(Error::Input(format!(
+        "{}: features \"png\" and \"jpeg\" were not enabled at compile-time",
+        path.display()
+    )))
+}
+
+fn read_raw(path: &Path, w: usize, h: usize) -> Result<ImgVec<BGRA8>, Error> {
+    let bytes = fs::read(path)?;
+    if bytes.len() != w * h * 4 {
+        return Err(Error::Input(format!(
+            "{}: {} bytes is not a {w}x{h} raw BGRA image, expected {} bytes",
+            path.display(),
+            bytes.len(),
+            w * h * 4
+        )));
+    }
+
+    let bytes = Secret::new(bytes);
+    let buf = bytes
+        .chunks_exact(4)
+        .map(|px| BGRA8 {
+            b: px[0],
+            g: px[1],
+            r: px[2],
+            a: px[3],
+        })
+        .collect();
+    Ok(ImgVec::new(buf, w, h))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw() {
+        let path = std::env::temp_dir().join(format!("i3lockr-{}.raw", std::process::id()));
+        fs::write(&path, [1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+
+        let img = read(&path, || Ok((2, 1))).unwrap();
+        assert_eq!((img.width(), img.height()), (2, 1));
+        assert_eq!(
+            img.buf()[1],
+            BGRA8 {
+                b: 5,
+                g: 6,
+                r: 7,
+                a: 8
+            }
+        );
+        assert!(read(&path, || Ok((2, 2))).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
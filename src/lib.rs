@@ -0,0 +1,86 @@
+//! Distort a screenshot and run i3lock.
+//!
+//! This is the library behind the `i3lockr` binary: the effects and the
+//! [`Pipeline`](pipeline::Pipeline) that runs them, the icon and text drawn on each
+//! monitor by a [`Decoration`](decorate::Decoration), monitor enumeration and
+//! launching i3lock or another [`Locker`](lock::Locker).
+//!
+//! ```no_run
+//! use i3lockr::lock::{I3lock, Locker};
+//! use i3lockr::pipeline::Pipeline;
+//! use imgref::ImgVec;
+//! use rgb::alt::BGRA8;
+//!
+//! # fn main() -> Result<(), i3lockr::Error> {
+//! let mut img = ImgVec::new(vec![BGRA8::default(); 1920 * 1080], 1920, 1080);
+//! let pipeline = Pipeline::parse(&["scale-down:2", "blur:10", "scale-up:2", "darken:20"])?;
+//! pipeline.run(&mut img.as_mut(), |effect, took| eprintln!("{effect} took {took:?}"));
+//! I3lock.lock(img.as_ref(), &[], &["--nofork".to_string()])?.wait()?;
+//! # Ok(())
+//! # }
+//! ```
+#![cfg_attr(test, allow(warnings))]
+#![warn(missing_docs)]
+
+mod macros;
+
+mod error;
+pub use error::Error;
+
+pub mod args;
+pub mod capture;
+pub mod discover;
+pub mod effect;
+pub mod input;
+pub mod lock;
+pub mod monitor;
+pub mod pipeline;
+pub mod secret;
+
+#[cfg(feature = "blur")]
+pub mod blur;
+
+#[cfg(feature = "brightness")]
+pub mod brightness;
+
+#[cfg(any(feature = "png", feature = "jpeg", feature = "text"))]
+pub mod decorate;
+
+#[cfg(feature = "png")]
+pub mod output;
+
+#[cfg(any(feature = "png", feature = "jpeg", feature = "text"))]
+pub mod overlay;
+
+#[cfg(feature = "pixelate")]
+pub mod pixelate;
+
+#[cfg(feature = "scale")]
+pub mod scale;
+
+#[cfg(feature = "text")]
+pub mod template;
+
+#[cfg(feature = "text")]
+pub mod text;
+
+/// Where i3lockr keeps files that mustn't outlive the session, such as the cached
+/// screenshot: `$XDG_RUNTIME_DIR/i3lockr`, or `None` if that isn't set.
+pub fn runtime_dir() -> Option<std::path::PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| std::path::PathBuf::from(dir).join(env!("CARGO_PKG_NAME")))
+}
+
+/// Where i3lockr keeps files between runs: `$XDG_CACHE_HOME/i3lockr`,
+/// falling back to `~/.cache/i3lockr`.
+pub fn cache_dir() -> std::path::PathBuf {
+    use std::{env, path::PathBuf};
+
+    env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(env::temp_dir)
+        .join(env!("CARGO_PKG_NAME"))
+}
@@ -0,0 +1,1090 @@
+//! Handing the image to a screen locker.
+
+#[cfg(feature = "png")]
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, Write};
+#[cfg(feature = "memfd")]
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use std::{env, error, fmt};
+
+use imgref::{ImgRef, ImgRefMut};
+use rgb::alt::BGRA8;
+use rgb::ComponentBytes;
+
+use crate::args::I3lockArgs;
+use crate::monitor::Monitor;
+use crate::secret;
+use crate::Error;
+
+/// How long a locker gets to fail before it's taken to have locked the screen, see [`Handoff`].
+const GRACE: Duration = Duration::from_secs(3);
+
+/// Lockers that can be given to [`open`].
+pub const LOCKERS: [&str; 5] = ["i3lock", "i3lock-color", "swaylock", "xsecurelock", "slock"];
+
+/// Why the screen couldn't be locked.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum LockError {
+    /// An unknown locker, or one that can't be used in this build or with this version
+    Unsupported(String),
+    /// The named locker isn't installed
+    NotFound(&'static str),
+    /// The arguments passed through can't be used with the locker
+    Args(String),
+    /// The named locker couldn't be started or sent the image
+    Run(&'static str, io::Error),
+    /// The locker exited with an error
+    Exit {
+        /// Name of the locker
+        name: &'static str,
+        /// Its exit code
+        code: i32,
+        /// What it printed to stderr
+        stderr: String,
+    },
+    /// The locker was killed by a signal
+    Signal {
+        /// Name of the locker
+        name: &'static str,
+        /// The signal
+        signal: i32,
+        /// What it printed to stderr
+        stderr: String,
+    },
+    /// Every locker tried failed, with why
+    AllFailed(Vec<(String, Error)>),
+}
+
+impl LockError {
+    /// The status i3lockr exits with after this error, for scripts to act on:
+    ///
+    /// - 3: the locker isn't installed
+    /// - 4: the locker can't be used with the arguments passed through
+    /// - 5: the locker exited with an error
+    /// - 6: the locker crashed, or was killed
+    /// - 1: anything else
+    ///
+    /// When every locker failed, it's the code of the first one that's installed.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::NotFound(_) => 3,
+            Self::Args(_) => 4,
+            Self::Exit { .. } => 5,
+            Self::Signal { .. } => 6,
+            Self::AllFailed(errors) => errors
+                .iter()
+                .map(|(_, e)| e.exit_code())
+                .find(|&code| code != 3)
+                .unwrap_or(3),
+            Self::Unsupported(_) | Self::Run(..) => 1,
+        }
+    }
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the last line is usually the one that says what went wrong
+        let said = |stderr: &str| match stderr.trim().lines().last() {
+            Some(line) => format!(": {}", line.trim()),
+            None => String::new(),
+        };
+        match self {
+            Self::Unsupported(e) | Self::Args(e) => f.write_str(e),
+            Self::NotFound(name) => write!(f, "{name} was not found in $PATH"),
+            Self::Run(name, e) => write!(f, "Failed to run {name}: {e}"),
+            Self::Exit { name, code, stderr } => {
+                write!(f, "{name} exited with code {code}{}", said(stderr))
+            }
+            Self::Signal {
+                name,
+                signal,
+                stderr,
+            } => write!(f, "{name} was killed by signal {signal}{}", said(stderr)),
+            Self::AllFailed(errors) => {
+                let names: Vec<_> = errors.iter().map(|(name, _)| name.as_str()).collect();
+                write!(f, "no locker worked, tried: {}", names.join(", "))
+            }
+        }
+    }
+}
+
+impl error::Error for LockError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Run(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<LockError> for Error {
+    fn from(e: LockError) -> Self {
+        Self::Lock(e)
+    }
+}
+
+/// A screen locker, and how to hand it the image.
+pub trait Locker {
+    /// Name as given to `--locker`.
+    fn name(&self) -> &'static str;
+
+    /// Lockers of the same flavor take the same arguments.
+    fn flavor(&self) -> &'static str {
+        self.name()
+    }
+
+    /// Whether the locker takes an image per output, cropped to each of the monitors
+    /// given to [`lock`](Locker::lock).
+    fn per_output(&self) -> bool {
+        false
+    }
+
+    /// Whether the locker forks once the screen is locked when run with `args`,
+    /// instead of staying until it's unlocked.
+    fn forks(&self, args: &[String]) -> bool;
+
+    /// Start the locker with `img`, cropped to each of `monitors` for lockers that
+    /// take an image per output, and `args` passed through.
+    ///
+    /// Returns once the locker has the image, so it can be wiped before [`Handoff::wait`].
+    fn lock(
+        &self,
+        img: ImgRef<BGRA8>,
+        monitors: &[Monitor],
+        args: &[String],
+    ) -> Result<Handoff, Error>;
+}
+
+/// Open the locker called `name`, one of [`LOCKERS`].
+pub fn open(name: &str) -> Result<Box<dyn Locker>, Error> {
+    match name {
+        "i3lock" => Ok(Box::new(I3lock)),
+        "i3lock-color" => Ok(Box::new(I3lockColor)),
+        "slock" => Ok(Box::new(Slock)),
+        #[cfg(feature = "png")]
+        "swaylock" => Ok(Box::new(Swaylock)),
+        #[cfg(feature = "png")]
+        "xsecurelock" => Ok(Box::new(Xsecurelock)),
+        #[cfg(not(feature = "png"))]
+        "swaylock" | "xsecurelock" => Err(LockError::Unsupported(format!(
+            "{name} needs feature \"png\", which was not enabled at compile-time"
+        ))
+        .into()),
+        _ => Err(LockError::Unsupported(format!(
+            "unknown locker \"{name}\", expected one of: {}",
+            LOCKERS.join(", ")
+        ))
+        .into()),
+    }
+}
+
+/// Lock with the first of the lockers called `names` that works, calling `tried`
+/// with the outcome of each attempt.
+///
+/// `img` is wiped as soon as a locker has it, or once the screen is locked while there
+/// are fallbacks left that would need it.
+///
+/// `args` are meant for the first locker, so they're only passed to those of the same flavor.
+pub fn lock_first<S, F>(
+    names: &[S],
+    img: &mut ImgRefMut<BGRA8>,
+    monitors: &[Monitor],
+    args: &[String],
+    mut tried: F,
+) -> Result<(), Error>
+where
+    S: AsRef<str>,
+    F: FnMut(&str, &Result<(), Error>),
+{
+    let mut flavor = None;
+    let mut errors = Vec::new();
+    for (i, name) in names.iter().enumerate() {
+        let name = name.as_ref();
+        let fallbacks = i + 1 < names.len();
+        let result = open(name).and_then(|locker| {
+            let args = match *flavor.get_or_insert(locker.flavor()) {
+                f if f == locker.flavor() => args,
+                _ => &[],
+            };
+            let mut handoff = locker.lock(img.as_ref(), monitors, args)?;
+            if fallbacks {
+                handoff.settle()?;
+            }
+            secret::wipe(img.buf_mut());
+            Ok(handoff.wait()?)
+        });
+        tried(name, &result);
+        match result {
+            Ok(()) => return Ok(()),
+            Err(e) => errors.push((name.to_string(), e)),
+        }
+    }
+    Err(LockError::AllFailed(errors).into())
+}
+
+/// [i3lock](https://github.com/i3/i3lock), reading the raw image from stdin,
+/// or a PNG before 2.12.
+///
+/// Options only i3lock-color understands are rejected, unless it turns out to be i3lock-color.
+pub struct I3lock;
+
+impl Locker for I3lock {
+    fn name(&self) -> &'static str {
+        "i3lock"
+    }
+
+    fn forks(&self, args: &[String]) -> bool {
+        I3lockArgs::parse(args).map_or(true, |args| !args.nofork())
+    }
+
+    fn lock(&self, img: ImgRef<BGRA8>, _: &[Monitor], args: &[String]) -> Result<Handoff, Error> {
+        lock_i3lock(self.name(), img, args, self.forks(args))
+    }
+}
+
+/// [i3lock-color](https://github.com/Raymo111/i3lock-color), run as `i3lock-color`.
+///
+/// It takes the image the same way as i3lock.
+pub struct I3lockColor;
+
+impl Locker for I3lockColor {
+    fn name(&self) -> &'static str {
+        "i3lock-color"
+    }
+
+    fn flavor(&self) -> &'static str {
+        "i3lock"
+    }
+
+    fn forks(&self, args: &[String]) -> bool {
+        I3lockArgs::parse_color(args).map_or(true, |args| !args.nofork())
+    }
+
+    fn lock(&self, img: ImgRef<BGRA8>, _: &[Monitor], args: &[String]) -> Result<Handoff, Error> {
+        lock_i3lock(self.name(), img, args, self.forks(args))
+    }
+}
+
+/// [swaylock](https://github.com/swaywm/swaylock), given a PNG per output.
+///
+/// Without monitor names, every output gets the whole image.
+#[cfg(feature = "png")]
+pub struct Swaylock;
+
+#[cfg(feature = "png")]
+impl Locker for Swaylock {
+    fn name(&self) -> &'static str {
+        "swaylock"
+    }
+
+    fn per_output(&self) -> bool {
+        true
+    }
+
+    fn forks(&self, args: &[String]) -> bool {
+        args.iter().take_while(|arg| *arg != "--").any(|arg| {
+            arg == "--daemonize"
+                || arg.strip_prefix('-').is_some_and(|flags| {
+                    // only a group of flags, not an option with its value attached
+                    flags.contains('f') && flags.chars().all(|c| SWAYLOCK_FLAGS.contains(c))
+                })
+        })
+    }
+
+    fn lock(
+        &self,
+        img: ImgRef<BGRA8>,
+        monitors: &[Monitor],
+        args: &[String],
+    ) -> Result<Handoff, Error> {
+        let mut cmd = Command::new(self.name());
+        // swaylock reads the images before it daemonizes, so they only need to last until it exits
+        let mut pngs = Vec::new();
+        if monitors.is_empty() || monitors.iter().any(|m| m.name.is_empty()) {
+            let png = TempPng::write(img)?;
+            cmd.arg("-i").arg(&png.0);
+            pngs.push(png);
+        } else {
+            for m in monitors {
+                let g = m.geometry;
+                let png = TempPng::write(img.sub_image(g.x, g.y, g.w, g.h))?;
+                let mut spec = OsString::from(format!("{}:", m.name));
+                spec.push(&png.0);
+                cmd.arg("-i").arg(spec);
+                pngs.push(png);
+            }
+        }
+
+        cmd.args(args);
+        let running = Running::spawn(self.name(), &mut cmd)?;
+        Ok(Handoff::new(running, self.forks(args)).keeping(pngs))
+    }
+}
+
+/// Short options of swaylock that don't take a value.
+#[cfg(feature = "png")]
+const SWAYLOCK_FLAGS: &str = "dehFfKkLlnrtuv";
+
+/// [xsecurelock](https://github.com/google/xsecurelock), showing a PNG with its
+/// `saver_mpv` screen saver.
+#[cfg(feature = "png")]
+pub struct Xsecurelock;
+
+#[cfg(feature = "png")]
+impl Locker for Xsecurelock {
+    fn name(&self) -> &'static str {
+        "xsecurelock"
+    }
+
+    fn forks(&self, _: &[String]) -> bool {
+        false
+    }
+
+    fn lock(&self, img: ImgRef<BGRA8>, _: &[Monitor], args: &[String]) -> Result<Handoff, Error> {
+        // the saver is started again whenever the screen wakes up, so keep the image until unlocked
+        let png = TempPng::write(img)?;
+        let mut cmd = Command::new(self.name());
+        cmd.env("I3LOCKR_IMAGE", &png.0)
+            .env("XSECURELOCK_SAVER", "saver_mpv")
+            .env(
+                "XSECURELOCK_LIST_VIDEOS_COMMAND",
+                "printf '%s\\n' \"$I3LOCKR_IMAGE\"",
+            )
+            .env("XSECURELOCK_IMAGE_DURATION_SECONDS", "inf")
+            .args(args);
+        let running = Running::spawn(self.name(), &mut cmd)?;
+        Ok(Handoff::new(running, self.forks(args)).keeping(vec![png]))
+    }
+}
+
+/// [slock](https://tools.suckless.org/slock/), which blanks the screen instead of
+/// showing the image.
+pub struct Slock;
+
+impl Locker for Slock {
+    fn name(&self) -> &'static str {
+        "slock"
+    }
+
+    fn forks(&self, _: &[String]) -> bool {
+        false
+    }
+
+    fn lock(&self, _: ImgRef<BGRA8>, _: &[Monitor], args: &[String]) -> Result<Handoff, Error> {
+        let mut cmd = Command::new(self.name());
+        cmd.args(args);
+        let running = Running::spawn(self.name(), &mut cmd)?;
+        Ok(Handoff::new(running, self.forks(args)))
+    }
+}
+
+/// What an installed i3lock can do, going by `i3lock --version`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Probe {
+    /// Major and minor version
+    pub version: (u32, u32),
+    /// Whether it's i3lock-color
+    pub color: bool,
+}
+
+impl Probe {
+    /// Whether it reads raw images with `--raw`, added in i3lock 2.12.
+    pub fn raw(&self) -> bool {
+        self.version >= (2, 12)
+    }
+
+    /// Parse the output of `i3lock --version`, such as "i3lock: version 2.13 © 2010 Michael
+    /// Stapelberg". i3lock-color's versions look like "2.13.c.5".
+    pub fn parse(s: &str) -> Option<Self> {
+        let version = s.split_once("version ")?.1.split_whitespace().next()?;
+        let mut parts = version.split('.');
+        let mut number = || {
+            let part = parts.next()?;
+            let digits = part
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(part.len());
+            part[..digits].parse().ok()
+        };
+        let version = (number()?, number()?);
+        Some(Self {
+            version,
+            color: parts.any(|part| part == "c"),
+        })
+    }
+}
+
+/// Run `name --version` to find out what it can do, remembering the answer for each
+/// binary until it changes.
+pub fn probe(name: &'static str) -> Result<Probe, Error> {
+    let path = env::var_os("PATH")
+        .iter()
+        .flat_map(env::split_paths)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+        .ok_or(LockError::NotFound(name))?;
+
+    // the binary's path and modification time, so an upgrade probes again
+    let key = fs::metadata(&path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+        .map(|mtime| format!("{}\t{}", path.display(), mtime.as_nanos()));
+    let cache = crate::cache_dir().join("lockers");
+    if let Some(probe) = key.as_deref().and_then(|key| read_probe(&cache, key)) {
+        return Ok(probe);
+    }
+
+    let output = Command::new(&path)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| LockError::Run(name, e))?;
+    // i3lock prints its version to stderr
+    let text = String::from_utf8_lossy(&output.stderr) + String::from_utf8_lossy(&output.stdout);
+    let probe = Probe::parse(&text).ok_or_else(|| {
+        LockError::Unsupported(format!(
+            "{name}: can't tell the version from \"{}\"",
+            text.trim()
+        ))
+    })?;
+
+    if let Some(key) = key {
+        // only a cache, it's fine if it can't be written
+        let _ = save_probe(&cache, &key, probe);
+    }
+    Ok(probe)
+}
+
+/// Look up `key` in the probe cache, a line per binary of "path, mtime, version, flavor".
+fn read_probe(cache: &Path, key: &str) -> Option<Probe> {
+    let cached = fs::read_to_string(cache).ok()?;
+    let line = cached
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix('\t'))?;
+    let (version, flavor) = line.split_once('\t')?;
+    let (major, minor) = version.split_once('.')?;
+    Some(Probe {
+        version: (major.parse().ok()?, minor.parse().ok()?),
+        color: flavor == "color",
+    })
+}
+
+fn save_probe(cache: &Path, key: &str, probe: Probe) -> io::Result<()> {
+    if let Some(dir) = cache.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let path = key.split('\t').next().unwrap_or(key);
+    let cached = fs::read_to_string(cache).unwrap_or_default();
+
+    // write then rename, so concurrent runs never see half a file
+    let tmp = cache.with_extension(format!("{}.tmp", process::id()));
+    let mut file = BufWriter::new(File::create(&tmp)?);
+    for line in cached.lines() {
+        if line.split('\t').next() != Some(path) {
+            writeln!(file, "{line}")?;
+        }
+    }
+    let (major, minor) = probe.version;
+    let flavor = if probe.color { "color" } else { "i3lock" };
+    writeln!(file, "{key}\t{major}.{minor}\t{flavor}")?;
+    file.flush()?;
+    drop(file);
+    fs::rename(&tmp, cache)
+}
+
+/// Run an i3lock compatible locker, handing it the image raw if it's new enough,
+/// or else as a PNG.
+fn lock_i3lock(
+    name: &'static str,
+    img: ImgRef<BGRA8>,
+    args: &[String],
+    forks: bool,
+) -> Result<Handoff, Error> {
+    // as i3lock's options, which only matter for an i3lock that isn't i3lock-color
+    let parsed = I3lockArgs::parse(args)?;
+    parsed.check(name)?;
+
+    let probe = probe(name)?;
+    if !probe.color {
+        if let Some(opt) = parsed.color_only() {
+            let (major, minor) = probe.version;
+            return Err(LockError::Args(format!(
+                "{name} {major}.{minor} doesn't support \"{opt}\", only i3lock-color does"
+            ))
+            .into());
+        }
+    }
+
+    if probe.raw() {
+        lock_raw(name, img, args, forks)
+    } else {
+        lock_png(name, img, args, forks)
+    }
+}
+
+/// Run an i3lock older than 2.12 with `img` in a PNG.
+#[cfg(feature = "png")]
+fn lock_png(
+    name: &'static str,
+    img: ImgRef<BGRA8>,
+    args: &[String],
+    forks: bool,
+) -> Result<Handoff, Error> {
+    // i3lock reads the image before it forks
+    let png = TempPng::write(img)?;
+    let mut cmd = Command::new(name);
+    cmd.arg("-i").arg(&png.0).args(args);
+    let running = Running::spawn(name, &mut cmd)?;
+    Ok(Handoff::new(running, forks).keeping(vec![png]))
+}
+
+#[cfg(not(feature = "png"))]
+fn lock_png(name: &'static str, _: ImgRef<BGRA8>, _: &[String], _: bool) -> Result<Handoff, Error> {
+    Err(LockError::Unsupported(format!(
+        "{name} is older than 2.12 so it needs a PNG, but feature \"png\" was not enabled at compile-time"
+    ))
+    .into())
+}
+
+/// Run an i3lock compatible locker with `img` piped to it raw.
+///
+/// When forking, i3lock exits once the screen is locked, so waiting for it
+/// still tells whether locking worked, see [`Handoff::wait`].
+///
+/// The image is handed over as stdin in a sealed memfd if possible, so the locker
+/// reads it at its own pace and never sees it half written, or else through a pipe.
+fn lock_raw(
+    name: &'static str,
+    img: ImgRef<BGRA8>,
+    args: &[String],
+    forks: bool,
+) -> Result<Handoff, Error> {
+    let (w, h) = (img.width(), img.height());
+    let mut cmd = Command::new(name);
+    cmd.args([
+        "-i",
+        "/dev/stdin",
+        //FIXME
+        format!("--raw={w}x{h}:native").as_str(),
+    ])
+    .args(args);
+
+    #[cfg(feature = "memfd")]
+    if let Ok(memfd) = sealed(img) {
+        cmd.stdin(memfd);
+        return Ok(Handoff::new(Running::spawn(name, &mut cmd)?, forks));
+    }
+
+    cmd.stdin(Stdio::piped());
+    let mut running = Running::spawn(name, &mut cmd)?;
+
+    // pass image bytes
+    let stdin = running
+        .child
+        .stdin
+        .as_mut()
+        .expect("Failed to take cmd.stdin.as_mut()");
+    let written = write_raw(img, stdin);
+    let handoff = Handoff::new(running, forks);
+
+    // a locker that refused its arguments stops reading, so its exit status says more
+    if let Err(e) = written {
+        handoff.wait()?;
+        return Err(LockError::Run(name, e).into());
+    }
+    Ok(handoff)
+}
+
+fn write_raw<W: Write>(img: ImgRef<BGRA8>, writer: &mut W) -> io::Result<()> {
+    let (w, h) = (img.width(), img.height());
+    if img.stride() == w {
+        writer.write_all(img.buf()[..w * h].as_bytes())
+    } else {
+        img.rows()
+            .try_for_each(|row| writer.write_all(row.as_bytes()))
+    }
+}
+
+/// `img` raw in a memfd sealed against any further change, and against being run.
+///
+/// The pages are locked into memory while they're written, but like any shared memory
+/// they can be swapped out afterwards, for as long as the locker holds the memfd.
+#[cfg(feature = "memfd")]
+fn sealed(img: ImgRef<BGRA8>) -> io::Result<File> {
+    let create = |flags| {
+        // SAFETY: the name is a valid C string, and the result is checked
+        unsafe {
+            libc::memfd_create(
+                c"i3lockr".as_ptr(),
+                libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING | flags,
+            )
+        }
+    };
+    // MFD_NOEXEC_SEAL is only known to Linux 6.3 and newer
+    let mut fd = create(libc::MFD_NOEXEC_SEAL);
+    if fd == -1 && io::Error::last_os_error().raw_os_error() == Some(libc::EINVAL) {
+        fd = create(0);
+    }
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: fd was just created and nothing else owns it
+    let file = unsafe { File::from_raw_fd(fd) };
+
+    let len = img.width() * img.height() * 4;
+    file.set_len(len as u64)?;
+    if len > 0 {
+        write_mapped(&file, img, len)?;
+    }
+
+    let seals = libc::F_SEAL_SEAL | libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE;
+    // SAFETY: fcntl on an fd owned by file, the result is checked
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+/// Write `img` into the `len` bytes of `file` through a mapping, locked into memory
+/// if possible, so no copy is left behind in a buffer.
+#[cfg(feature = "memfd")]
+fn write_mapped(file: &File, img: ImgRef<BGRA8>, len: usize) -> io::Result<()> {
+    // SAFETY: a new shared mapping of the whole file, the result is checked
+    let addr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED,
+            file.as_raw_fd(),
+            0,
+        )
+    };
+    if addr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the mapping is len bytes, and only used here until it's unmapped
+    let buf = unsafe { std::slice::from_raw_parts_mut(addr.cast::<u8>(), len) };
+
+    let locked = secret::lock(buf).is_ok();
+    let written = write_raw(img, &mut &mut buf[..]);
+    if locked {
+        secret::unlock(buf);
+    }
+    // SAFETY: buf isn't used past here, and sealing against writes needs it unmapped
+    unsafe { libc::munmap(addr, len) };
+    written
+}
+
+/// A locker that's been started, with its stderr going to a file so a failure can say why.
+struct Running {
+    name: &'static str,
+    child: Child,
+    stderr: Option<File>,
+}
+
+impl Running {
+    fn spawn(name: &'static str, cmd: &mut Command) -> Result<Self, LockError> {
+        // a file rather than a pipe, since a forking locker keeps stderr open until unlocked
+        let stderr = runtime_file("stderr").ok();
+        if let Some(file) = stderr.as_ref().and_then(|file| file.try_clone().ok()) {
+            cmd.stderr(file);
+        }
+        let child = cmd.spawn().map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => LockError::NotFound(name),
+            _ => LockError::Run(name, e),
+        })?;
+        Ok(Self {
+            name,
+            child,
+            stderr,
+        })
+    }
+
+    /// Whether the locker exiting with `status` means it worked.
+    fn check(&mut self, status: ExitStatus) -> Result<(), LockError> {
+        let name = self.name;
+        let stderr = self.stderr.take().map(read_stderr).unwrap_or_default();
+
+        if status.success() {
+            // pass on any warnings
+            eprint!("{stderr}");
+            Ok(())
+        } else if let Some(code) = status.code() {
+            Err(LockError::Exit { name, code, stderr })
+        } else {
+            Err(LockError::Signal {
+                name,
+                signal: status.signal().unwrap(),
+                stderr,
+            })
+        }
+    }
+
+    fn exited_within(&mut self, timeout: Duration) -> Result<Option<ExitStatus>, LockError> {
+        let start = Instant::now();
+        loop {
+            let status = self
+                .child
+                .try_wait()
+                .map_err(|e| LockError::Run(self.name, e))?;
+            if status.is_some() || start.elapsed() >= timeout {
+                return Ok(status);
+            }
+            sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// A locker that's been handed the image, along with any files it may still read.
+///
+/// A locker that's still running after [`GRACE`] is taken to have locked the screen,
+/// even one expected to fork, which is then left to it in the foreground.
+pub struct Handoff {
+    running: Running,
+    forks: bool,
+    settled: bool,
+    exited: bool,
+    #[cfg(feature = "png")]
+    _pngs: Vec<TempPng>,
+}
+
+impl Handoff {
+    fn new(running: Running, forks: bool) -> Self {
+        Self {
+            running,
+            forks,
+            settled: false,
+            exited: false,
+            #[cfg(feature = "png")]
+            _pngs: Vec::new(),
+        }
+    }
+
+    /// Keep `pngs` until the locker's done.
+    #[cfg(feature = "png")]
+    fn keeping(mut self, pngs: Vec<TempPng>) -> Self {
+        self._pngs = pngs;
+        self
+    }
+
+    /// Give the locker [`GRACE`] to fail, while a fallback could still be tried instead.
+    fn settle(&mut self) -> Result<(), LockError> {
+        if !self.settled {
+            self.settled = true;
+            if let Some(status) = self.running.exited_within(GRACE)? {
+                self.exited = true;
+                self.running.check(status)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait until the screen is locked if the locker forks, or else until it's unlocked.
+    pub fn wait(mut self) -> Result<(), LockError> {
+        if self.forks {
+            return self.settle();
+        }
+        if !self.exited {
+            let name = self.running.name;
+            let status = self
+                .running
+                .child
+                .wait()
+                .map_err(|e| LockError::Run(name, e))?;
+            self.running.check(status)?;
+        }
+        Ok(())
+    }
+}
+
+/// What's been written to `file` so far, up to 64 KiB.
+fn read_stderr(mut file: File) -> String {
+    let mut bytes = Vec::new();
+    let _ = file
+        .rewind()
+        .and_then(|()| file.take(64 * 1024).read_to_end(&mut bytes));
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// A new path in `$XDG_RUNTIME_DIR`, or the temporary directory if it's unset.
+fn runtime_path(extension: &str) -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let dir = env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map_or_else(env::temp_dir, PathBuf::from);
+    dir.join(format!(
+        "{}-{}-{}.{extension}",
+        env!("CARGO_PKG_NAME"),
+        process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// A file that only the current user can read, removed as soon as it's created.
+fn runtime_file(extension: &str) -> io::Result<File> {
+    let path = runtime_path(extension);
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?;
+    fs::remove_file(&path)?;
+    Ok(file)
+}
+
+/// A PNG in `$XDG_RUNTIME_DIR` that only the current user can read, removed when dropped.
+#[cfg(feature = "png")]
+struct TempPng(PathBuf);
+
+#[cfg(feature = "png")]
+impl TempPng {
+    fn write(img: ImgRef<BGRA8>) -> Result<Self, Error> {
+        let path = runtime_path("png");
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)?;
+
+        let png = Self(path);
+        let mut file = secret::Buffered::new(file);
+        crate::output::encode(img, &mut file)?;
+        file.flush()?;
+        Ok(png)
+    }
+}
+
+#[cfg(feature = "png")]
+impl Drop for TempPng {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forking(args: &[&str]) -> bool {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        I3lock.forks(&args)
+    }
+
+    #[test]
+    fn forking_short() {
+        assert!(!forking(&["-a", "-n", "-b"]));
+        assert!(!forking(&["-e", "-n", "-b"]));
+    }
+
+    #[test]
+    fn forking_short_grouped() {
+        // -c takes the rest of the group as its value, so -n is never seen, as with getopt
+        assert!(forking(&["-abcdefghijklmnopqrstuvwxyz"]));
+        assert!(!forking(&["-bdenptu", "win"]));
+    }
+
+    #[test]
+    fn forking_long() {
+        assert!(!forking(&["--abc", "--nofork", "--xyz"]));
+        assert!(!forking(&["--clock", "--nofork", "--time-str", "%H"]));
+    }
+
+    #[test]
+    fn _forking() {
+        assert!(forking(&[
+            "--abc",
+            "--image",
+            "image-nofork.png",
+            "--nofork.png"
+        ]));
+        assert!(forking(&["--clock", "--color", "-n", "-p-n"]));
+    }
+
+    #[test]
+    fn forking_positional() {
+        assert!(forking(&["--abc", "--", "--nofork"]));
+        assert!(forking(&["--clock", "--", "--nofork"]));
+    }
+
+    #[test]
+    #[cfg(feature = "png")]
+    fn forking_lockers() {
+        let args = ["-f".to_string()];
+        assert!(Swaylock.forks(&args));
+        assert!(!Swaylock.forks(&[]));
+        assert!(!Swaylock.forks(&["-cf00f00".to_string()]));
+        assert!(!Xsecurelock.forks(&args));
+        assert!(open("vlock").is_err());
+    }
+
+    #[test]
+    fn parse_version() {
+        let i3lock = "i3lock: version 2.15 © 2010 Michael Stapelberg";
+        assert_eq!(
+            Probe::parse(i3lock),
+            Some(Probe {
+                version: (2, 15),
+                color: false
+            })
+        );
+        let color = "i3lock: version 2.13.c.5 © 2010 Michael Stapelberg, © 2015 Cassandra Fox";
+        assert_eq!(
+            Probe::parse(color),
+            Some(Probe {
+                version: (2, 13),
+                color: true
+            })
+        );
+        assert!(!Probe::parse("i3lock: version 2.11-non-git").unwrap().raw());
+        assert_eq!(Probe::parse("i3lock: unknown option"), None);
+    }
+
+    #[test]
+    fn probe_cache() {
+        let cache = std::env::temp_dir().join(format!("i3lockr-{}.probe", std::process::id()));
+        let probe = Probe {
+            version: (2, 13),
+            color: true,
+        };
+        save_probe(&cache, "/usr/bin/i3lock\t1", probe).unwrap();
+        assert_eq!(read_probe(&cache, "/usr/bin/i3lock\t1"), Some(probe));
+        save_probe(
+            &cache,
+            "/usr/bin/i3lock\t2",
+            Probe {
+                color: false,
+                ..probe
+            },
+        )
+        .unwrap();
+        assert_eq!(read_probe(&cache, "/usr/bin/i3lock\t1"), None);
+        assert_eq!(fs::read_to_string(&cache).unwrap().lines().count(), 1);
+        fs::remove_file(&cache).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "memfd")]
+    fn sealed_memfd() {
+        use std::io::Read;
+
+        let data: Vec<_> = (0..6u8).map(|i| BGRA8::new(i, i, i, 255)).collect();
+        // two columns of a three column image, so the rows aren't contiguous
+        let img = ImgRef::new_stride(&data, 2, 2, 3);
+        let mut memfd = sealed(img).unwrap();
+
+        let mut bytes = Vec::new();
+        memfd.read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 16);
+        assert_eq!(&bytes[8..12], [3, 3, 3, 255]);
+        assert!(memfd.write_all(&[0]).is_err());
+        assert!(memfd.set_len(0).is_err());
+    }
+
+    #[test]
+    fn locker_failures() {
+        let run = |name, script| {
+            Handoff::new(
+                Running::spawn(name, Command::new(name).args(["-c", script]))?,
+                false,
+            )
+            .wait()
+        };
+
+        let e = run("sh", "echo warning >&2; echo oops >&2; exit 1").unwrap_err();
+        assert!(
+            matches!(e, LockError::Exit { code: 1, ref stderr, .. } if stderr == "warning\noops\n")
+        );
+        assert_eq!(e.to_string(), "sh exited with code 1: oops");
+        assert_eq!(e.exit_code(), 5);
+
+        let e = run("sh", "kill -9 $$").unwrap_err();
+        assert!(matches!(e, LockError::Signal { signal: 9, .. }));
+        assert_eq!(e.exit_code(), 6);
+
+        let e = run("i3lockr-no-such-locker", "").unwrap_err();
+        assert!(matches!(e, LockError::NotFound(_)));
+        assert_eq!(e.exit_code(), 3);
+
+        assert!(run("sh", "exit 0").is_ok());
+
+        // the first locker that's installed decides, if any is
+        let all = LockError::AllFailed(vec![
+            ("i3lock".to_string(), LockError::NotFound("i3lock").into()),
+            ("slock".to_string(), LockError::NotFound("slock").into()),
+        ]);
+        assert_eq!(all.exit_code(), 3);
+        let all = LockError::AllFailed(vec![
+            ("i3lock".to_string(), LockError::NotFound("i3lock").into()),
+            ("sh".to_string(), run("sh", "exit 2").unwrap_err().into()),
+        ]);
+        assert_eq!(all.exit_code(), 5);
+    }
+
+    #[test]
+    fn forking_locker_in_foreground() {
+        let run = |script| {
+            Handoff::new(
+                Running::spawn("sh", Command::new("sh").args(["-c", script]))?,
+                true,
+            )
+            .wait()
+        };
+
+        // still running once the grace is up, so it's taken to have locked in the foreground
+        let start = Instant::now();
+        assert!(run("sleep 30").is_ok());
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        assert!(run("exit 0").is_ok());
+        assert!(matches!(
+            run("exit 1"),
+            Err(LockError::Exit { code: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn settle() {
+        let spawn = |script| Running::spawn("sh", Command::new("sh").args(["-c", script])).unwrap();
+
+        // failing straight away leaves the image to a fallback
+        let mut handoff = Handoff::new(spawn("exit 1"), false);
+        assert!(handoff.settle().is_err());
+
+        let mut handoff = Handoff::new(spawn("sleep 5; exit 1"), false);
+        assert!(handoff.settle().is_ok());
+        assert!(matches!(
+            handoff.wait(),
+            Err(LockError::Exit { code: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn lock_first_tries_all() {
+        let mut img = imgref::ImgVec::new(vec![BGRA8::default()], 1, 1);
+        let mut tried = Vec::new();
+        let result = lock_first(
+            &["vlock", "xlock"],
+            &mut img.as_mut(),
+            &[],
+            &[],
+            |name, result| tried.push((name.to_string(), result.is_ok())),
+        );
+        assert!(result.is_err());
+        assert_eq!(
+            tried,
+            [("vlock".to_string(), false), ("xlock".to_string(), false)]
+        );
+    }
+}
@@ -1,3 +1,4 @@
+#[doc(hidden)]
 #[macro_export]
 macro_rules! timer_start {
     ($timer:ident) => {
@@ -5,6 +6,7 @@ macro_rules! timer_start {
     };
 }
 
+#[doc(hidden)]
 #[macro_export]
 macro_rules! timer_time {
     ($s:expr, $timer:ident) => {
@@ -12,6 +14,7 @@ macro_rules! timer_time {
     };
 }
 
+#[doc(hidden)]
 #[macro_export]
 macro_rules! warn_disabled {
     ($s:expr) => {
@@ -24,34 +27,3 @@ macro_rules! warn_disabled {
         );
     };
 }
-
-#[macro_export]
-macro_rules! time_routine {
-    ($operand:ident, $F:ident, $Arg:expr, $feat:literal) => {
-        if let Some(arg) = $Arg {
-        #[cfg(feature = $feat)]
-        {
-                let timer = Instant::now();
-
-                $operand.$F(arg);
-
-                debug!("`{}.{}({})` took {:#?}", stringify!($operand), stringify!($F), arg, timer.elapsed());
-            }
-
-        #[cfg(not(feature = $feat))]
-        {
-            eprintln!(
-                "{}",
-                format!(
-                    "Feature {} was not enabled at compile-time. Skipping {}.", stringify!($feat), stringify!($F)
-                )
-            );
-        }
-        }
-    };
-
-    ($operand:ident, $F:ident, $Arg:expr, $feat:literal, $($more:tt)*) => {
-        time_routine!($operand, $F, $Arg, $feat);
-        time_routine!($operand, $($more)*);
-    };
-}
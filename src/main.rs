@@ -1,59 +1,72 @@
 #![cfg_attr(test, allow(warnings))]
 
-use std::error::Error;
-use std::io::ErrorKind::WouldBlock;
-use std::io::{self, Write};
-use std::num::TryFromIntError;
-use std::os::unix::process::ExitStatusExt;
-use std::process::{Command, ExitStatus, Stdio};
-use std::thread::sleep;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
 use std::time::{Duration, Instant};
 
-use clap::Parser;
-use getargs::{Opt, Options};
-use imgref::ImgRefMut;
-use rgb::{ComponentBytes, FromSlice};
-use scrap::{Capturer, Display, Frame};
+use clap::{CommandFactory, FromArgMatches};
+use rgb::alt::BGRA8;
 
-#[cfg(any(feature = "png", feature = "jpeg"))]
 use xcb::Connection;
 
-mod cli;
-mod macros;
-
-use cli::Cli;
+use i3lockr::args::I3lockArgs;
+use i3lockr::capture::{self, CaptureBackend, CaptureError, Fallback, Screenshot};
+use i3lockr::discover;
+use i3lockr::effect::{self, Effect};
+use i3lockr::input;
+use i3lockr::lock;
+use i3lockr::monitor::{self, Candidate, Monitor, Selector};
+use i3lockr::pipeline::Pipeline;
+use i3lockr::{timer_start, timer_time, Error};
 
-#[cfg(any(feature = "png", feature = "jpeg"))]
-use imagefmt::ColFmt;
-#[cfg(any(feature = "png", feature = "jpeg"))]
-use xcb::randr;
-#[cfg(any(feature = "png", feature = "jpeg"))]
-use xcb::Xid;
-
-#[cfg(feature = "scale")]
-mod scale;
-#[cfg(feature = "scale")]
-use scale::Scale;
+mod cli;
+#[cfg(feature = "config")]
+mod config;
 
-#[cfg(feature = "blur")]
-mod blur;
-#[cfg(feature = "blur")]
-use blur::Blur;
+use cli::{CaptureFallback, Cli};
 
-#[cfg(feature = "brightness")]
-mod brightness;
-#[cfg(feature = "brightness")]
-use brightness::BrightnessAdj;
+#[cfg(any(feature = "png", feature = "jpeg", feature = "text"))]
+use i3lockr::decorate::Decoration;
 
 #[cfg(any(feature = "png", feature = "jpeg"))]
-mod overlay;
-#[cfg(any(feature = "png", feature = "jpeg"))]
-use overlay::Compose;
+use i3lockr::decorate::Icon;
+
+#[cfg(feature = "text")]
+use i3lockr::decorate::Label;
+
+#[cfg(feature = "text")]
+use i3lockr::template::Template;
+
+#[cfg(feature = "text")]
+use i3lockr::text::{self, Text, TextStyle};
+
+/// Color to lock with when nothing better is left.
+const PLAIN: BGRA8 = BGRA8 {
+    b: 0,
+    g: 0,
+    r: 0,
+    a: 255,
+};
+
+/// Set for the run of i3lockr locking each X screen, to the screen.
+const SCREEN_VAR: &str = "I3LOCKR_SCREEN";
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn run() -> Result<(), Error> {
     timer_start!(everything);
     // parse args, handle custom `--version`
-    let args = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut args = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
     if args.version {
         eprintln!(
             "{} v{} compiled for '{}' at {} ({}@{})",
@@ -67,6 +80,16 @@ fn main() -> Result<(), Box<dyn Error>> {
         return Ok(());
     }
 
+    // layer the config file under the command line
+    #[cfg(feature = "config")]
+    if let Err(e) = args.load_config(&matches) {
+        recover(args.strict, e, "ignoring the config file")?;
+    }
+    #[cfg(not(feature = "config"))]
+    if args.config.is_some() || args.profile.is_some() {
+        i3lockr::warn_disabled!("config");
+    }
+
     // init debug macro
     macro_rules! debug {
         ($($arg:tt)*) => {
@@ -78,259 +101,459 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     debug!("Found args: {:#?}", args);
 
-    #[cfg(any(feature = "png", feature = "jpeg"))]
-    let (conn, screen_num) = Connection::connect(None)?;
-
-    // setup scrap
-    timer_start!(scrap);
-    let disp = Display::primary()?;
-    let mut capture = Capturer::new(disp)?;
-    let (w, h) = (capture.width(), capture.height());
-    timer_time!("Setting up scrap", scrap);
-
-    // take the screenshot
-    timer_start!(screenshot);
-    let mut buffer: Frame;
-    loop {
-        match capture.frame() {
-            Ok(buf) => {
-                buffer = buf;
-                break;
-            }
-            Err(e) => {
-                if e.kind() == WouldBlock {
-                    sleep(Duration::from_millis(33));
-                    continue;
+    // with several X screens, write each from its own run, which only sees its screen
+    let screen = env::var(SCREEN_VAR).ok();
+    match screen {
+        Some(ref screen) => {
+            debug!("Writing X screen {}", screen);
+            args.output = args
+                .output
+                .take()
+                .map(|path| discover::per_screen(&path, screen));
+        }
+        None => {
+            if let Some((display, screens)) = screens() {
+                if args.output.is_some() {
+                    debug!("Writing {} X screens of {}", screens, display);
+                    let result = write_screens(&args, &display, screens);
+                    timer_time!("Everything", everything);
+                    return result;
                 }
+                // a locker for another screen couldn't grab the keyboard and pointer
+                // this one holds for the whole display, so only this screen is covered
+                debug!(
+                    "Locking only the default of {} X screens of {}",
+                    screens, display
+                );
             }
         }
     }
-    timer_time!("Capturing screenshot", screenshot);
-
-    // convert to imgref
-    timer_start!(convert);
-    let buf_bgra = buffer.as_bgra_mut();
-    let mut screenshot = ImgRefMut::new(buf_bgra, w, h);
-    timer_time!("Converting image", convert);
-
-    // scaling is unsafe
-    unsafe {
-        time_routine!(
-            screenshot,
-            scale_down,
-            args.factor,
-            "scale",
-            blur,
-            args.radius,
-            "blur",
-            scale_up,
-            args.factor,
-            "scale",
-            brighten,
-            args.bright,
-            "brightness",
-            darken,
-            args.dark,
-            "brightness"
-        );
-    }
 
-    // overlay/invert on each monitor
-    if let Some(ref path) = args.path {
-        #[cfg(any(feature = "png", feature = "jpeg"))]
-        {
-            timer_start!(decode);
-            let image = imagefmt::read(path, ColFmt::BGRA)?;
-            let image = imgref::ImgRef::new(image.buf.as_bgra(), image.w, image.h);
-            timer_time!("Decoding overlay image", decode);
-
-            // get handle on monitors
-            let screen = conn
-                .get_setup()
-                .roots()
-                .nth(usize::try_from(screen_num)?)
-                .unwrap_or_else(|| unreachable!());
-
-            let cookie = conn.send_request(&randr::GetScreenResources {
-                window: screen.root(),
-            });
-            let reply = conn.wait_for_reply(cookie)?;
-
-            let monitors = reply
-                .crtcs()
-                .iter()
-                .filter_map(|crtc| {
-                    let cookie = conn.send_request(&randr::GetCrtcInfo {
-                        crtc: *crtc,
-                        config_timestamp: reply.timestamp(),
-                    });
-                    conn.wait_for_reply(cookie).ok()
-                })
-                .enumerate()
-                .filter(|(i, m)| !m.mode().is_none() && !args.ignore.contains(i))
-                .map(|(_, m)| -> Result<_, _> {
-                    Ok((
-                        Ok::<_, TryFromIntError>(usize::from(m.width()))?,
-                        Ok::<_, TryFromIntError>(usize::from(m.height()))?,
-                        usize::try_from(m.x())?,
-                        usize::try_from(m.y())?,
-                    ))
-                })
-                .collect::<Result<Vec<(_, _, _, _)>, TryFromIntError>>()?;
-
-            for (w, h, x, y) in monitors {
-                let (x_off, y_off) = if args.pos.is_empty() {
-                    if image.width() > w || image.height() > h {
-                        eprintln!(
-                            "Your image is larger than your monitor, image positions may be off!"
-                        );
-                    }
-                    (
-                        w / 2 - image.width() / 2 + x,
-                        h / 2 - image.height() / 2 + y,
-                    )
-                } else {
-                    unsafe {
-                        (
-                            wrap_to_screen(*args.pos.get_unchecked(0), w + x),
-                            wrap_to_screen(*args.pos.get_unchecked(1), h + y),
-                        )
-                    }
-                };
+    // a broken setting still locks unless --strict, there's nothing to lock with --output
+    let strict = args.strict || args.output.is_some();
 
-                debug!(
-                    "Calculated image position on monitor: ({},{})",
-                    x_off, y_off
-                );
+    // catch mistakes in the effects and text before taking the screenshot
+    let pipeline = match pipeline(&args) {
+        Ok(pipeline) => Some(pipeline),
+        Err(e) => {
+            recover(strict, e, "locking without the screenshot")?;
+            None
+        }
+    };
+    #[cfg(feature = "text")]
+    let template = match args.text.as_deref().map(Template::parse).transpose() {
+        Ok(template) => template,
+        Err(e) => {
+            recover(strict, e, "locking without the text")?;
+            None
+        }
+    };
+
+    let layout = match monitor::layout(&args.monitors) {
+        Ok(layout) => layout,
+        Err(e) => {
+            recover(strict, e, "ignoring --monitors")?;
+            Vec::new()
+        }
+    };
+
+    // only connect to X when RandR is needed
+    let mut x = None;
 
-                timer_start!(overlay);
-                if args.invert {
-                    screenshot.invert(Some(image), x_off, y_off);
-                } else {
-                    screenshot.compose(image, x_off, y_off);
+    let fallback = args.capture_fallback.and_then(|fallback| match fallback {
+        CaptureFallback::Color([r, g, b, a]) => Some(Fallback::Color(BGRA8 { b, g, r, a })),
+        CaptureFallback::Cached => {
+            let path = cache_path(screen.as_deref());
+            if path.is_none() {
+                eprintln!("Warning: --capture-fallback cached needs $XDG_RUNTIME_DIR, ignoring it");
+            }
+            path.map(Fallback::Cached)
+        }
+    });
+
+    // pick how to get the image
+    timer_start!(setup);
+    let mut backend = pipeline.as_ref().map(|_| backend(&args, &layout, &mut x));
+    if let Some(Ok(ref backend)) = backend {
+        timer_time!(format!("Setting up {}", backend.name()), setup);
+    }
+
+    // take the screenshot, or lock with the fallback if that fails
+    timer_start!(grab);
+    let timeout = Duration::from_millis(args.capture_timeout);
+    let mut fallback_img;
+    let result = match backend {
+        Some(Ok(ref mut backend)) => backend.capture(timeout).map(Some),
+        Some(Err(e)) => Err(e),
+        // the effects are broken, so the screenshot can't be shown
+        None => Ok(None),
+    };
+    let (mut capture, captured) = match result {
+        Ok(Some(capture)) => (capture, true),
+        result => {
+            let plain = Fallback::Color(PLAIN);
+            let instead = match (result, &fallback) {
+                (Err(e), Some(fallback)) => {
+                    eprintln!("Error: {e}, locking with the fallback instead");
+                    fallback
                 }
-                timer_time!("Overlaying image", overlay);
+                (Err(e), None) => {
+                    recover(strict, e, "locking with a plain color instead")?;
+                    &plain
+                }
+                (Ok(_), fallback) => fallback.as_ref().unwrap_or(&plain),
+            };
+            fallback_img = match instead.image(|| screen_size(&layout, &mut x)) {
+                Ok(img) => img,
+                Err(e) => {
+                    recover(strict, e, "locking with a plain color instead")?;
+                    plain.image(|| screen_size(&layout, &mut x))?
+                }
+            };
+            let (w, h) = (fallback_img.width(), fallback_img.height());
+            (
+                Screenshot::new(fallback_img.buf_mut().as_mut_slice(), w, h),
+                false,
+            )
+        }
+    };
+    debug!("Screenshot locked into memory: {}", capture.locked());
+    let mut screenshot = capture.as_img();
+    let (w, h) = (screenshot.width(), screenshot.height());
+    timer_time!("Capturing screenshot", grab);
+
+    // a fallback image doesn't need effects, a cached one already has them
+    if let (true, Some(pipeline)) = (captured, pipeline) {
+        // run the effects in order
+        pipeline.run(&mut screenshot, |effect, took| {
+            debug!("`{}` took {:#?}", effect, took);
+        });
+
+        if let (Some(Fallback::Cached(_)), true) = (&fallback, pipeline.is_empty()) {
+            eprintln!("Warning: not caching the image for --capture-fallback, it has no effects to hide what's on screen");
+        } else if let Some(Fallback::Cached(ref path)) = fallback {
+            timer_start!(cache);
+            if let Err(e) = capture::save_cache(path, screenshot.as_ref()) {
+                eprintln!("Failed to cache the image at {}: {e}", path.display());
             }
+            timer_time!("Caching image", cache);
         }
-        #[cfg(not(any(feature = "png", feature = "jpeg")))]
-        warn_disabled!("png/jpeg overlay");
+    }
+
+    // get handle on monitors, for the icon and text and lockers that take an image per output
+    let decorated = args.path.is_some() || args.text.is_some();
+    let per_output = args.output.is_none()
+        && args
+            .locker
+            .iter()
+            .any(|name| lock::open(name).is_ok_and(|locker| locker.per_output()));
+    let (candidates, all) = if decorated || per_output {
+        match candidates(&args, layout, &mut x, (w, h)) {
+            Ok(found) => found,
+            Err(e) => {
+                recover(strict, e, "locking without the icon and text")?;
+                (Vec::new(), Vec::new())
+            }
+        }
+    } else {
+        (Vec::new(), Vec::new())
+    };
+    // mirrored monitors show the same part of the image, so only draw there once
+    let drawn = monitor::dedup(&candidates);
+    // ignored monitors still need their part of the image
+    let monitors: Vec<_> = all.into_iter().map(|c| c.monitor).collect();
+    debug!(
+        "Drawing on {} places for {} monitors",
+        drawn.len(),
+        monitors.len()
+    );
+
+    // draw the icon and text, keeping whatever was drawn if that fails
+    #[cfg(any(feature = "png", feature = "jpeg", feature = "text"))]
+    {
+        timer_start!(draw);
+        let decoration = decoration(
+            &args,
+            strict,
+            #[cfg(feature = "text")]
+            template,
+        )?;
+        if let Err(e) = decoration.draw(&mut screenshot, &drawn) {
+            recover(strict, e, "locking without the rest of the icon and text")?;
+        }
+        timer_time!("Drawing icon and text", draw);
+    }
+    #[cfg(not(any(feature = "png", feature = "jpeg")))]
+    if args.path.is_some() {
+        i3lockr::warn_disabled!("png/jpeg overlay");
     } else if args.invert {
-        #[cfg(any(feature = "png", feature = "jpeg"))]
+        i3lockr::warn_disabled!("invert");
+    }
+    #[cfg(not(feature = "text"))]
+    if args.text.is_some() {
+        i3lockr::warn_disabled!("text");
+    }
+
+    // write the image instead of locking
+    if let Some(ref path) = args.output {
+        #[cfg(feature = "png")]
         {
-            timer_start!(invert);
-            screenshot.invert(None, 0, 0);
-            timer_time!("Inverting image", invert);
+            timer_start!(write);
+            i3lockr::output::write_png(screenshot.as_ref(), path)?;
+            timer_time!("Writing image", write);
+            timer_time!("Everything", everything);
+            return Ok(());
         }
-        #[cfg(not(any(feature = "png", feature = "jpeg")))]
-        warn_disabled!("invert");
+        #[cfg(not(feature = "png"))]
+        return Err(Error::Output(format!(
+            "{}: feature \"png\" was not enabled at compile-time",
+            path.display()
+        )));
     }
 
-    //TODO draw text
-
-    // check if we're forking
-    timer_start!(fork);
-    let forking = forking(args.i3lock.iter().map(String::as_str));
-    timer_time!("Checking for nofork", fork);
-
-    // call i3lock
-    debug!("Calling i3lock with args: {:?}", args.i3lock);
-    let mut cmd = Command::new("i3lock")
-        .args([
-            "-i",
-            "/dev/stdin",
-            //FIXME
-            format!("--raw={w}x{h}:native").as_str(),
-        ])
-        .args(args.i3lock)
-        .stdin(Stdio::piped())
-        .spawn()?;
-
-    // pass image bytes
-    cmd.stdin
-        .as_mut()
-        .expect("Failed to take cmd.stdin.as_mut()")
-        .write_all(screenshot.into_buf().as_bytes())?;
-
+    // call the lockers in turn until one works, or only the first with --strict
+    let lockers = if strict {
+        &args.locker[..1]
+    } else {
+        &args.locker[..]
+    };
+    debug!("Calling {:?} with args: {:?}", lockers, args.i3lock);
+    debug!("As i3lock options: {:?}", I3lockArgs::parse(&args.i3lock));
+    let result = lock::lock_first(
+        lockers,
+        &mut screenshot,
+        &monitors,
+        &args.i3lock,
+        |name, result| match result {
+            Ok(()) => debug!("Locked with {}", name),
+            Err(e) => eprintln!("Error: {e}"),
+        },
+    );
     timer_time!("Everything", everything);
+    result
+}
 
-    if forking {
-        match cmd.try_wait() {
-            Ok(None) => Ok(()),
-            Ok(Some(status)) => status_to_result(status),
-            Err(e) => Err(e.into()),
+/// Report `e` and carry on locking with less, unless `strict` says to give up.
+fn recover(strict: bool, e: Error, instead: &str) -> Result<(), Error> {
+    if strict {
+        return Err(e);
+    }
+    eprintln!("Error: {e}, {instead}");
+    Ok(())
+}
+
+/// Open the capture backend asked for, or the first that works.
+fn backend(
+    args: &Cli,
+    layout: &[Monitor],
+    x: &mut Option<(Connection, i32)>,
+) -> Result<Box<dyn CaptureBackend>, Error> {
+    match (&args.input, args.capture_backend.as_deref()) {
+        (Some(path), None | Some("file")) => {
+            let img = input::read(path, || screen_size(layout, x))?;
+            Ok(Box::new(capture::File::new(img)))
         }
-    } else {
-        debug!("Asked i3lock not to fork, calling wait()");
-        match cmd.wait() {
-            Ok(status) => status_to_result(status),
-            Err(e) => Err(e.into()),
+        (Some(_), Some(name)) => Err(CaptureError::Backend(format!(
+            "--input can't be used with capture backend \"{name}\""
+        ))
+        .into()),
+        (None, Some("file")) => {
+            Err(CaptureError::Backend("capture backend \"file\" needs --input".to_string()).into())
         }
+        (None, Some(name)) => capture::open(name),
+        (None, None) => capture::detect(),
     }
 }
 
-fn status_to_result(status: ExitStatus) -> Result<(), Box<dyn Error>> {
-    if status.success() {
-        Ok(())
-    } else if let Some(code) = status.code() {
-        Err(io::Error::from_raw_os_error(code).into())
+/// Monitors from the layout if one was given, or else RandR, checked to fit in a `w`x`h`
+/// image: those --ignore-monitors doesn't pick, then all of them.
+fn candidates(
+    args: &Cli,
+    layout: Vec<Monitor>,
+    x: &mut Option<(Connection, i32)>,
+    size: (usize, usize),
+) -> Result<(Vec<Candidate>, Vec<Candidate>), Error> {
+    let ignore = args
+        .ignore
+        .iter()
+        .map(|s| s.parse())
+        .collect::<Result<Vec<Selector>, _>>()?;
+    let all = if layout.is_empty() {
+        let (conn, screen_num) = connect(x)?;
+        let edid = ignore.iter().any(|sel| matches!(sel, Selector::Edid(_)));
+        discover::candidates(conn, *screen_num, edid)?.0
     } else {
-        Err(format!("Killed by signal: {}", status.signal().unwrap()).into())
+        monitor::numbered(layout)
+    };
+    monitor::fit(&all, size)?;
+
+    // names change less than indexes, but can still be mistyped
+    let mut candidates = all.clone();
+    let found: Vec<_> = all.iter().map(ToString::to_string).collect();
+    for sel in monitor::skip(&mut candidates, &ignore) {
+        eprintln!(
+            "Warning: --ignore-monitors \"{sel}\" matches no monitor, there's: {}",
+            found.join(", ")
+        );
     }
+    Ok((candidates, all))
 }
 
-// credit: @williewillus#8490
-#[cfg(any(feature = "png", feature = "jpeg"))]
-const fn wrap_to_screen(idx: isize, len: usize) -> usize {
-    let pos = idx.unsigned_abs() % len;
-    if pos == 0 {
-        0
-    } else {
-        len - pos
+/// Size of the monitor layout if one was given, or else the X screen.
+fn screen_size(
+    layout: &[Monitor],
+    x: &mut Option<(Connection, i32)>,
+) -> Result<(usize, usize), Error> {
+    match monitor::bounds(layout) {
+        Some(size) => Ok(size),
+        None => {
+            let (conn, screen_num) = connect(x)?;
+            Ok(discover::screen_size(conn, *screen_num))
+        }
     }
 }
 
-fn forking<'a, I>(args: I) -> bool
-where
-    I: Iterator<Item = &'a str>,
-{
-    let mut opts = Options::new(args);
-    while let Some(opt) = opts.next_opt().unwrap() {
-        match opt {
-            Opt::Short('n') | Opt::Long("nofork") => return false,
-            _ => continue,
-        };
-    }
-    true
+/// Where --capture-fallback=cached keeps the last image, of `screen` if there are several.
+/// It's kept out of the cache directory, which outlives the session.
+fn cache_path(screen: Option<&str>) -> Option<PathBuf> {
+    let path = i3lockr::runtime_dir()?.join("fallback.bgra");
+    Some(match screen {
+        Some(screen) => discover::per_screen(&path, screen),
+        None => path,
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// The display and how many X screens it has, if there's more than one.
+fn screens() -> Option<(String, usize)> {
+    let display = env::var("DISPLAY").ok()?;
+    let (conn, _) = Connection::connect(None).ok()?;
+    let screens = discover::screens(&conn);
+    (screens > 1).then_some((display, screens))
+}
 
-    #[test]
-    fn forking_short() {
-        assert!(!forking(["-a", "-n", "-b"].into_iter()))
+/// Run i3lockr again for each of `screens` X screens to write it to its own file, all
+/// at once, and wait for every run.
+fn write_screens(args: &Cli, display: &str, screens: usize) -> Result<(), Error> {
+    if args.output.as_deref() == Some(Path::new("-")) {
+        return Err(Error::Output(format!(
+            "stdout can't hold the {screens} X screens, write them to a file instead"
+        )));
     }
 
-    #[test]
-    fn forking_short_grouped() {
-        assert!(!forking(["-abcdefghijklmnopqrstuvwxyz"].into_iter()))
+    let exe = env::current_exe()?;
+    let runs: Vec<_> = (0..screens)
+        .map(|screen| {
+            let run = Command::new(&exe)
+                .args(env::args_os().skip(1))
+                .env("DISPLAY", discover::screen_display(display, screen))
+                .env(SCREEN_VAR, screen.to_string())
+                .spawn();
+            (screen, run)
+        })
+        .collect();
+    discover::first_failure(
+        runs.into_iter()
+            .map(|(screen, run)| (screen, run.and_then(|mut child| child.wait()))),
+    )
+}
+
+/// Connect to X the first time it's needed.
+fn connect(x: &mut Option<(Connection, i32)>) -> Result<&(Connection, i32), Error> {
+    if x.is_none() {
+        *x = Some(discover::connect()?);
     }
+    Ok(x.as_ref().unwrap_or_else(|| unreachable!()))
+}
 
-    #[test]
-    fn forking_long() {
-        assert!(!forking(["--abc", "--nofork", "--xyz"].into_iter()))
+/// The effects to run, from --effects or else the individual flags.
+fn pipeline(args: &Cli) -> Result<Pipeline, Error> {
+    if !args.effects.is_empty() {
+        return Pipeline::parse(&args.effects);
     }
 
-    #[test]
-    fn _forking() {
-        assert!(forking(
-            ["--abc", "--image", "image-nofork.png", "--nofork.png"].into_iter()
-        ))
+    // blurring is cheaper on the scaled down image
+    let blur: Vec<_> = args
+        .radius
+        .map(|r| Box::new(effect::Blur(r)) as Box<dyn Effect>)
+        .into_iter()
+        .collect();
+    let mut effects = match args.factor {
+        Some(f) => effect::scaled(f, blur),
+        None => blur,
+    };
+    effects.extend(
+        [
+            args.block
+                .map(|b| Box::new(effect::Pixelate(b)) as Box<dyn Effect>),
+            args.bright.map(|a| Box::new(effect::Brighten(a)) as _),
+            args.dark.map(|a| Box::new(effect::Darken(a)) as _),
+        ]
+        .into_iter()
+        .flatten(),
+    );
+    Pipeline::new(effects)
+}
+
+/// The icon and text to draw, from the arguments. One that can't be loaded is left
+/// out unless `strict` says to give up.
+#[cfg(any(feature = "png", feature = "jpeg", feature = "text"))]
+fn decoration(
+    args: &Cli,
+    strict: bool,
+    #[cfg(feature = "text")] template: Option<Template>,
+) -> Result<Decoration, Error> {
+    let pos = |pos: &[isize]| match *pos {
+        [x, y] => Some((x, y)),
+        _ => None,
+    };
+    let mut decoration = Decoration {
+        invert: args.invert,
+        rotate: args.rotate,
+        ..Decoration::default()
+    };
+
+    #[cfg(any(feature = "png", feature = "jpeg"))]
+    if let Some(ref path) = args.path {
+        match Icon::open(path, pos(&args.pos)) {
+            Ok(icon) => decoration.icon = Some(icon),
+            Err(e) => {
+                recover(strict, e, "locking without the icon")?;
+                // without its mask, inverting would turn the whole screenshot
+                decoration.invert = false;
+            }
+        }
     }
 
-    #[test]
-    fn forking_positional() {
-        assert!(forking(["--abc", "--", "--nofork"].into_iter()))
+    #[cfg(feature = "text")]
+    if let Some(template) = template {
+        let style = TextStyle {
+            size: args.text_size,
+            color: text::bgra(args.text_color),
+            alpha: args.text_alpha,
+            outline: args
+                .text_outline
+                .map(|px| (px.get(), text::bgra(args.text_outline_color))),
+            shadow: match args.text_shadow[..] {
+                [dx, dy] => Some((dx, dy, text::bgra(args.text_shadow_color))),
+                _ => None,
+            },
+        };
+        let text = args
+            .font
+            .as_deref()
+            .ok_or_else(|| Error::Font("for --text: none was given with --font".to_string()))
+            .and_then(|font| Text::new(font, style));
+        match text {
+            Ok(text) => {
+                decoration.label = Some(Label {
+                    template,
+                    text,
+                    pos: pos(&args.text_pos),
+                });
+            }
+            Err(e) => recover(strict, e, "locking without the text")?,
+        }
     }
+
+    Ok(decoration)
 }
@@ -0,0 +1,515 @@
+//! Monitors, from a layout given by the user or found by [`crate::discover`], and
+//! picking out those to skip.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Error;
+
+/// A rectangle on the X screen, in pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Geometry {
+    /// Offset from the left of the screen
+    pub x: usize,
+    /// Offset from the top of the screen
+    pub y: usize,
+    /// Width
+    pub w: usize,
+    /// Height
+    pub h: usize,
+}
+
+impl fmt::Display for Geometry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.w, self.h, self.x, self.y)
+    }
+}
+
+/// Parse "WxH+X+Y" like xrandr prints it.
+impl FromStr for Geometry {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || Error::Monitor(format!("\"{s}\" is not a monitor, expected \"WxH+X+Y\""));
+        let (w, h, x, y) = signed(s).ok_or_else(err)?;
+        let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y)) else {
+            return Err(err());
+        };
+        Ok(Self { x, y, w, h })
+    }
+}
+
+/// Width, height and offset of "WxH+X+Y", where the offset may be negative as in
+/// "1920x1080+-1920+0".
+fn signed(s: &str) -> Option<(usize, usize, isize, isize)> {
+    let (size, offset) = s.split_once('+')?;
+    let (w, h) = size.split_once('x')?;
+    let (x, y) = offset.split_once('+')?;
+
+    let (w, h) = (w.parse().ok()?, h.parse().ok()?);
+    if w == 0 || h == 0 {
+        return None;
+    }
+    Some((w, h, x.parse().ok()?, y.parse().ok()?))
+}
+
+/// Which way a monitor is turned, like xrandr's `--rotate`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rotation {
+    /// Upright
+    #[default]
+    Normal,
+    /// A quarter turn counterclockwise
+    Left,
+    /// Upside down
+    Inverted,
+    /// A quarter turn clockwise
+    Right,
+}
+
+impl Rotation {
+    /// Width and height of something `w`x`h` on the screen as the panel has them, before
+    /// it's turned.
+    pub fn unturned<T>(self, w: T, h: T) -> (T, T) {
+        match self {
+            Self::Left | Self::Right => (h, w),
+            Self::Normal | Self::Inverted => (w, h),
+        }
+    }
+
+    /// Where the top-left corner of a `w`x`h` rectangle at (`x`, `y`) on a panel `size`
+    /// big ends up once the panel is turned, e.g. to keep text upright on it.
+    pub const fn place(
+        self,
+        size: (usize, usize),
+        (x, y, w, h): (usize, usize, usize, usize),
+    ) -> (usize, usize) {
+        let (pw, ph) = size;
+        match self {
+            Self::Normal => (x, y),
+            // the top of the panel faces left
+            Self::Left => (y, pw - x - w),
+            Self::Inverted => (pw - x - w, ph - y - h),
+            Self::Right => (ph - y - h, x),
+        }
+    }
+}
+
+/// A monitor and its place on the X screen.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Monitor {
+    /// RandR monitor or output name, e.g. "eDP-1", or "" if it has none
+    pub name: String,
+    /// Whether it's the primary monitor
+    pub primary: bool,
+    /// Where it is, already turned by `rotation`
+    pub geometry: Geometry,
+    /// Which way it's turned
+    pub rotation: Rotation,
+    /// Width and height in millimeters, if known
+    pub physical_mm: Option<(u32, u32)>,
+}
+
+impl Monitor {
+    /// An upright monitor called `name` at `geometry`, with nothing else known about it.
+    pub fn new(name: impl Into<String>, geometry: Geometry) -> Self {
+        Self {
+            name: name.into(),
+            geometry,
+            ..Self::default()
+        }
+    }
+}
+
+impl fmt::Display for Monitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.name.is_empty() {
+            write!(f, "{}:", self.name)?;
+        }
+        self.geometry.fmt(f)
+    }
+}
+
+/// Parse "WxH+X+Y" like xrandr prints it, optionally prefixed with "name:".
+impl FromStr for Monitor {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, geometry) = s.rsplit_once(':').unwrap_or(("", s));
+        let geometry = geometry.parse().map_err(|_| {
+            Error::Monitor(format!("\"{s}\" is not a monitor, expected \"WxH+X+Y\""))
+        })?;
+        Ok(Self::new(name, geometry))
+    }
+}
+
+/// Parse the monitors given with `--monitors`, moved so the layout starts at the top-left
+/// of the image when some are left of or above the origin, as xrandr would place them.
+pub fn layout<S: AsRef<str>>(specs: &[S]) -> Result<Vec<Monitor>, Error> {
+    let parsed = specs
+        .iter()
+        .map(|s| {
+            let s = s.as_ref();
+            let (name, geometry) = s.rsplit_once(':').unwrap_or(("", s));
+            signed(geometry).map(|g| (name, g)).ok_or_else(|| {
+                Error::Monitor(format!("\"{s}\" is not a monitor, expected \"WxH+X+Y\""))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let left = parsed
+        .iter()
+        .map(|(_, (_, _, x, _))| *x)
+        .fold(0, isize::min);
+    let top = parsed
+        .iter()
+        .map(|(_, (_, _, _, y))| *y)
+        .fold(0, isize::min);
+    Ok(parsed
+        .into_iter()
+        .map(|(name, (w, h, x, y))| {
+            let geometry = Geometry {
+                x: x.abs_diff(left),
+                y: y.abs_diff(top),
+                w,
+                h,
+            };
+            Monitor::new(name, geometry)
+        })
+        .collect())
+}
+
+/// How `--ignore-monitors` picks a monitor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+    /// Index in the order monitors were found, or in the layout given with `--monitors`
+    Index(usize),
+    /// RandR monitor or output name, e.g. "eDP-1"
+    Name(String),
+    /// Monitor name, serial number or PNP ID from the EDID, e.g. "edid:DELL U2415"
+    Edid(String),
+}
+
+/// Parse an index, "edid:" and what to look for in the EDID, or else an output name.
+impl FromStr for Selector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(edid) = s.strip_prefix("edid:") {
+            if edid.is_empty() {
+                return Err(Error::Monitor(format!(
+                    "\"{s}\" is missing what to look for in the EDID"
+                )));
+            }
+            Ok(Self::Edid(edid.to_owned()))
+        } else if let Ok(i) = s.parse() {
+            Ok(Self::Index(i))
+        } else if s.is_empty() {
+            Err(Error::Monitor("empty monitor name".into()))
+        } else {
+            Ok(Self::Name(s.to_owned()))
+        }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Index(i) => write!(f, "{i}"),
+            Self::Name(name) => f.write_str(name),
+            Self::Edid(edid) => write!(f, "edid:{edid}"),
+        }
+    }
+}
+
+impl Selector {
+    /// Whether this picks `candidate`.
+    pub fn picks(&self, candidate: &Candidate) -> bool {
+        match self {
+            Self::Index(i) => *i == candidate.index,
+            Self::Name(name) => *name == candidate.monitor.name,
+            Self::Edid(s) => candidate.edid.as_ref().is_some_and(|edid| edid.matches(s)),
+        }
+    }
+}
+
+/// What a monitor says about itself in its EDID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edid {
+    /// PNP ID, the manufacturer's three letters then the product code in hex, e.g. "DELA0B1"
+    pub pnp_id: String,
+    /// Monitor name, e.g. "DELL U2415"
+    pub name: Option<String>,
+    /// Serial number, if it's given as text
+    pub serial: Option<String>,
+}
+
+impl Edid {
+    /// Parse the base block of an EDID.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        const HEADER: [u8; 8] = [0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0];
+        if bytes.len() < 128 || bytes[..8] != HEADER {
+            return None;
+        }
+
+        // three letters of five bits each, 1 is 'A'
+        let id = u16::from_be_bytes([bytes[8], bytes[9]]);
+        let letter = |shift: u16| char::from(b'@' + ((id >> shift) & 0x1f) as u8);
+        let product = u16::from_le_bytes([bytes[10], bytes[11]]);
+        let mut edid = Self {
+            pnp_id: format!("{}{}{}{product:04X}", letter(10), letter(5), letter(0)),
+            name: None,
+            serial: None,
+        };
+
+        // four 18 byte descriptors, the text ones end with a newline or run to the end
+        for desc in bytes[54..126].chunks_exact(18) {
+            if desc[..3] != [0, 0, 0] {
+                continue;
+            }
+            let text = desc[5..].split(|&b| b == b'\n').next().unwrap_or_default();
+            let text = String::from_utf8_lossy(text).trim().to_owned();
+            match desc[3] {
+                0xfc => edid.name = Some(text),
+                0xff => edid.serial = Some(text),
+                _ => {}
+            }
+        }
+        Some(edid)
+    }
+
+    /// Whether `s` is the monitor name, serial number or PNP ID, ignoring case.
+    pub fn matches(&self, s: &str) -> bool {
+        [Some(&self.pnp_id), self.name.as_ref(), self.serial.as_ref()]
+            .into_iter()
+            .flatten()
+            .any(|field| field.eq_ignore_ascii_case(s))
+    }
+}
+
+impl fmt::Display for Edid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(ref name) => write!(f, "{name}, {}", self.pnp_id),
+            None => f.write_str(&self.pnp_id),
+        }
+    }
+}
+
+/// A monitor that `--ignore-monitors` might pick.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// Index in the order monitors were found, or in the layout
+    pub index: usize,
+    /// Where it is, and its output name
+    pub monitor: Monitor,
+    /// Its EDID, if it was asked for and the monitor has one
+    pub edid: Option<Edid>,
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.monitor.name.as_str() {
+            "" => write!(f, "{}", self.index)?,
+            name => write!(f, "{name} ({})", self.index)?,
+        }
+        if let Some(ref edid) = self.edid {
+            write!(f, " [{edid}]")?;
+        }
+        Ok(())
+    }
+}
+
+/// Size of the smallest image that holds every monitor in `layout`.
+pub fn bounds(layout: &[Monitor]) -> Option<(usize, usize)> {
+    layout
+        .iter()
+        .map(|m| (m.geometry.x + m.geometry.w, m.geometry.y + m.geometry.h))
+        .reduce(|(w, h), (x, y)| (w.max(x), h.max(y)))
+}
+
+/// The monitors of a layout given by the user, indexed in the order given.
+pub fn numbered(layout: Vec<Monitor>) -> Vec<Candidate> {
+    layout
+        .into_iter()
+        .enumerate()
+        .map(|(index, monitor)| Candidate {
+            index,
+            monitor,
+            edid: None,
+        })
+        .collect()
+}
+
+/// Check that every one of `candidates` fits in a `w`x`h` image.
+pub fn fit(candidates: &[Candidate], (w, h): (usize, usize)) -> Result<(), Error> {
+    match candidates.iter().map(|c| &c.monitor).find(|m| {
+        let Geometry { x, y, w: mw, h: mh } = m.geometry;
+        x + mw > w || y + mh > h
+    }) {
+        Some(m) => Err(Error::Monitor(format!(
+            "monitor {m} doesn't fit in the {w}x{h} image"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Drop the candidates any of `ignore` picks, returning those of `ignore` that picked none.
+pub fn skip<'a>(candidates: &mut Vec<Candidate>, ignore: &'a [Selector]) -> Vec<&'a Selector> {
+    let unused = ignore
+        .iter()
+        .filter(|sel| !candidates.iter().any(|c| sel.picks(c)))
+        .collect();
+    candidates.retain(|c| !ignore.iter().any(|sel| sel.picks(c)));
+    unused
+}
+
+/// Where to draw on the candidates left, once per place even if several show it.
+///
+/// Mirrored monitors share a place on the screen, and drawing on each would blend the
+/// icon over itself. Monitors made of several CRTCs are already one with RandR 1.5.
+pub fn dedup(candidates: &[Candidate]) -> Vec<Monitor> {
+    let mut places: Vec<Monitor> = Vec::new();
+    for c in candidates {
+        if !places.iter().any(|p| p.geometry == c.monitor.geometry) {
+            places.push(c.monitor.clone());
+        }
+    }
+    places
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let m: Monitor = "1920x1080+2560+0".parse().unwrap();
+        assert_eq!(
+            m.geometry,
+            Geometry {
+                x: 2560,
+                y: 0,
+                w: 1920,
+                h: 1080
+            }
+        );
+        assert_eq!(
+            (m.name.as_str(), m.primary, m.rotation),
+            ("", false, Rotation::Normal)
+        );
+        let m: Monitor = "DP-1:1280x1024+0+56".parse().unwrap();
+        assert_eq!(m.name, "DP-1");
+        assert_eq!(m.to_string(), "DP-1:1280x1024+0+56");
+
+        assert!("1920x1080".parse::<Monitor>().is_err());
+        assert!("1920x1080+0".parse::<Monitor>().is_err());
+        assert!("0x1080+0+0".parse::<Monitor>().is_err());
+        assert!("1920x1080+-5+0".parse::<Monitor>().is_err());
+    }
+
+    #[test]
+    fn negative() {
+        let moved = layout(&["DP-1:1920x1080+-1920+-200", "eDP-1:2560x1440+0+0"]).unwrap();
+        assert_eq!(moved[0].to_string(), "DP-1:1920x1080+0+0");
+        assert_eq!(moved[1].to_string(), "eDP-1:2560x1440+1920+200");
+        // already at the origin, so left alone
+        let kept = layout(&["1920x1080+100+0"]).unwrap();
+        assert_eq!(kept[0].geometry.x, 100);
+        assert!(layout(&["1920x1080+-x+0"]).is_err());
+    }
+
+    #[test]
+    fn rotations() {
+        // a 1080x1920 monitor turned left is a 1920x1080 panel
+        let panel = Rotation::Left.unturned(1080, 1920);
+        assert_eq!(panel, (1920, 1080));
+        // 200x100 text at the bottom middle of the panel, which is on the right once turned left
+        let text = (860, 980, 200, 100);
+        assert_eq!(Rotation::Normal.place(panel, text), (860, 980));
+        assert_eq!(Rotation::Left.place(panel, text), (980, 860));
+        assert_eq!(Rotation::Right.place(panel, text), (0, 860));
+        assert_eq!(Rotation::Inverted.place(panel, text), (860, 0));
+    }
+
+    fn edid(name: &str) -> Vec<u8> {
+        let mut edid = vec![0; 128];
+        edid[..8].copy_from_slice(&[0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0]);
+        // "DEL", product 0xa0b1
+        edid[8..12].copy_from_slice(&[0x10, 0xac, 0xb1, 0xa0]);
+        edid[72..77].copy_from_slice(&[0, 0, 0, 0xfc, 0]);
+        edid[77..77 + name.len()].copy_from_slice(name.as_bytes());
+        edid[77 + name.len()] = b'\n';
+        edid
+    }
+
+    #[test]
+    fn parse_edid() {
+        let edid = Edid::parse(&edid("DELL U2415")).unwrap();
+        assert_eq!(edid.pnp_id, "DELA0B1");
+        assert_eq!(edid.name.as_deref(), Some("DELL U2415"));
+        assert_eq!(edid.serial, None);
+        assert!(edid.matches("dell u2415"));
+        assert!(edid.matches("DELA0B1"));
+        assert!(Edid::parse(&[0; 128]).is_none());
+    }
+
+    #[test]
+    fn ignore() {
+        let sel = |s: &str| s.parse::<Selector>().unwrap();
+        assert_eq!(sel("2"), Selector::Index(2));
+        assert_eq!(sel("eDP-1"), Selector::Name("eDP-1".into()));
+        assert_eq!(sel("edid:DELL U2415").to_string(), "edid:DELL U2415");
+        assert!("edid:".parse::<Selector>().is_err());
+
+        let candidate = |index, name: &str, edid| Candidate {
+            index,
+            monitor: format!("{name}:1920x1080+{}+0", index * 1920)
+                .parse()
+                .unwrap(),
+            edid,
+        };
+        let mut candidates = vec![
+            candidate(0, "eDP-1", None),
+            candidate(1, "HDMI-A-0", Edid::parse(&edid("DELL U2415"))),
+            candidate(3, "DP-1", None),
+        ];
+        let ignore = [sel("eDP-1"), sel("edid:DELL U2415"), sel("3"), sel("DP-2")];
+        assert_eq!(skip(&mut candidates, &ignore), [&ignore[3]]);
+        assert!(candidates.is_empty());
+        assert_eq!(
+            candidate(1, "HDMI-A-0", Edid::parse(&edid("DELL U2415"))).to_string(),
+            "HDMI-A-0 (1) [DELL U2415, DELA0B1]"
+        );
+    }
+
+    #[test]
+    fn mirrors() {
+        let candidate = |index, monitor: &str| Candidate {
+            index,
+            monitor: monitor.parse().unwrap(),
+            edid: None,
+        };
+
+        // a projector mirroring the laptop screen
+        let candidates = [
+            candidate(0, "eDP-1:1920x1080+0+0"),
+            candidate(1, "HDMI-A-0:1920x1080+0+0"),
+            candidate(2, "DP-1:2560x1440+1920+0"),
+        ];
+        let places: Vec<_> = dedup(&candidates).iter().map(ToString::to_string).collect();
+        assert_eq!(places, ["eDP-1:1920x1080+0+0", "DP-1:2560x1440+1920+0"]);
+    }
+
+    #[test]
+    fn fits() {
+        let layout = layout(&["1920x1080+0+0", "DP-1:1280x1024+1920+0"]).unwrap();
+        assert_eq!(bounds(&layout), Some((3200, 1080)));
+        assert_eq!(bounds(&[]), None);
+
+        let candidates = numbered(layout);
+        assert_eq!(candidates[1].index, 1);
+        assert!(fit(&candidates, (3200, 1080)).is_ok());
+        assert!(fit(&candidates, (1920, 1080)).is_err());
+    }
+}
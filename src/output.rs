@@ -0,0 +1,81 @@
+//! Writing the processed screenshot to a file instead of locking.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use imagefmt::{ColFmt, ColType};
+use imgref::ImgRef;
+use rgb::alt::BGRA8;
+use rgb::ComponentBytes;
+
+use crate::secret;
+use crate::Error;
+
+/// Write `img` as a PNG to `path`, or to stdout if `path` is "-".
+///
+/// The alpha channel is dropped, since screenshots don't have a meaningful one.
+pub fn write_png(img: ImgRef<BGRA8>, path: &Path) -> Result<(), Error> {
+    if path == Path::new("-") {
+        let mut stdout = secret::Buffered::new(io::stdout().lock());
+        encode(img, &mut stdout)?;
+        return stdout.flush().map_err(Error::from);
+    }
+
+    check_extension(path)?;
+    let mut file = secret::Buffered::new(File::create(path)?);
+    encode(img, &mut file)?;
+    file.flush().map_err(Error::from)
+}
+
+/// Only PNG can be written, imagefmt decodes JPEG but has no encoder for it.
+fn check_extension(path: &Path) -> Result<(), Error> {
+    let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    if ext.eq_ignore_ascii_case("png") {
+        Ok(())
+    } else if ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("jpeg") {
+        Err(Error::Output(format!(
+            "{}: JPEG can't be written, only PNG, use a \".png\" file name",
+            path.display()
+        )))
+    } else {
+        Err(Error::Output(format!(
+            "{}: only PNG output is supported, use a \".png\" file name",
+            path.display()
+        )))
+    }
+}
+
+/// Encode `img` as a PNG into `writer`.
+///
+/// Give it a [`secret::Buffered`] rather than a `BufWriter`, so no copy is left in its
+/// buffer. imagefmt's own scratch rows are freed without being wiped, which can't be
+/// helped from out here.
+pub(crate) fn encode<W: Write>(img: ImgRef<BGRA8>, writer: &mut W) -> Result<(), Error> {
+    let (w, h, stride) = (img.width(), img.height(), img.stride());
+    // the last row may stop short of a full stride
+    let buf = &img.buf()[..(stride * h).saturating_sub(stride - w)];
+    imagefmt::png::write(
+        writer,
+        w,
+        h,
+        ColFmt::BGRA,
+        buf.as_bytes(),
+        ColType::Color,
+        Some(stride * 4),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extensions() {
+        assert!(check_extension(Path::new("lock.PNG")).is_ok());
+        let e = check_extension(Path::new("lock.jpg")).unwrap_err();
+        assert!(e.to_string().contains("JPEG"), "{e}");
+        assert!(check_extension(Path::new("lock")).is_err());
+    }
+}
@@ -1,7 +1,10 @@
+//! Overlaying icons and text on the screenshot.
+
 use blend_srgb::blend::blend_srgb8;
 
 use imgref::ImgRef;
 use imgref::ImgRefMut;
+use imgref::ImgVec;
 
 #[cfg(feature = "threads")]
 use rayon::prelude::*;
@@ -9,10 +12,36 @@ use rayon::prelude::*;
 use rgb::alt::BGRA8;
 use rgb::ColorComponentMap;
 
+use crate::monitor::Rotation;
+
 const MASK_THRESHOLD: u8 = 127;
 
+/// `img` turned like a monitor with `rotation`, so it's upright on that monitor's panel.
+pub fn turn(img: ImgRef<BGRA8>, rotation: Rotation) -> ImgVec<BGRA8> {
+    let (w, h) = (img.width(), img.height());
+    let (tw, th) = rotation.unturned(w, h);
+    // sized up front so nothing is left behind by growing it
+    let mut buf = Vec::with_capacity(tw * th);
+    for ty in 0..th {
+        for tx in 0..tw {
+            let (x, y) = match rotation {
+                Rotation::Normal => (tx, ty),
+                Rotation::Left => (w - 1 - ty, tx),
+                Rotation::Inverted => (w - 1 - tx, h - 1 - ty),
+                Rotation::Right => (ty, h - 1 - tx),
+            };
+            buf.push(img[(x, y)]);
+        }
+    }
+    ImgVec::new(buf, tw, th)
+}
+
+/// Drawing one image over another.
 pub trait Compose {
+    /// Alpha blend `top` over this image with its top-left corner at (`x`, `y`).
     fn compose(&mut self, top: ImgRef<BGRA8>, x: usize, y: usize);
+    /// Invert the colors under the opaque pixels of `mask` placed at (`x`, `y`),
+    /// or the whole image without a mask.
     fn invert(&mut self, mask: Option<ImgRef<BGRA8>>, x: usize, y: usize);
 }
 
@@ -105,3 +134,30 @@ impl Compose for ImgRefMut<'_, BGRA8> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turns() {
+        let px = |b| BGRA8 {
+            b,
+            g: 0,
+            r: 0,
+            a: 255,
+        };
+        // 1 2 3
+        // 4 5 6
+        let img = ImgVec::new((1..=6).map(px).collect(), 3, 2);
+        let turned = |rotation| {
+            let img = turn(img.as_ref(), rotation);
+            let buf: Vec<_> = img.pixels().map(|px| px.b).collect();
+            (img.width(), buf)
+        };
+        assert_eq!(turned(Rotation::Normal), (3, vec![1, 2, 3, 4, 5, 6]));
+        assert_eq!(turned(Rotation::Left), (2, vec![3, 6, 2, 5, 1, 4]));
+        assert_eq!(turned(Rotation::Inverted), (3, vec![6, 5, 4, 3, 2, 1]));
+        assert_eq!(turned(Rotation::Right), (2, vec![4, 1, 5, 2, 6, 3]));
+    }
+}
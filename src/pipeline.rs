@@ -0,0 +1,152 @@
+//! Running effects on the screenshot in order.
+
+use std::time::{Duration, Instant};
+
+use imgref::ImgRefMut;
+
+use rgb::alt::BGRA8;
+
+use crate::effect::{Effect, Registry};
+use crate::Error;
+
+/// Effects to run on the screenshot, in order.
+#[derive(Debug)]
+pub struct Pipeline(Vec<Box<dyn Effect>>);
+
+impl Pipeline {
+    /// Parse "name:argument" specs with the built-in effects.
+    pub fn parse<S: AsRef<str>>(specs: &[S]) -> Result<Self, Error> {
+        let registry = Registry::builtin();
+        let effects = specs
+            .iter()
+            .map(|spec| registry.parse(spec.as_ref()))
+            .collect::<Result<_, _>>()
+            .map_err(Error::Effect)?;
+        Self::new(effects)
+    }
+
+    /// Check that `effects` can run in this order.
+    pub fn new(effects: Vec<Box<dyn Effect>>) -> Result<Self, Error> {
+        validate(&effects).map_err(Error::Effect)?;
+        Ok(Self(effects))
+    }
+
+    /// Whether there are no effects, leaving the screenshot as it was.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Apply each effect in turn, reporting how long it took.
+    pub fn run<F>(&self, img: &mut ImgRefMut<BGRA8>, mut timed: F)
+    where
+        F: FnMut(&dyn Effect, Duration),
+    {
+        for effect in &self.0 {
+            let timer = Instant::now();
+            effect.apply(img);
+            timed(effect.as_ref(), timer.elapsed());
+        }
+    }
+}
+
+/// Scaling down leaves everything outside the top-left corner undefined, so each
+/// scale-down must be undone by a scale-up with the same factor before the next one.
+fn validate(effects: &[Box<dyn Effect>]) -> Result<(), String> {
+    let mut down: Option<&dyn Effect> = None;
+    for effect in effects {
+        let effect = effect.as_ref();
+        match (effect.name(), down) {
+            ("scale-down", None) => down = Some(effect),
+            ("scale-up", Some(d)) if effect.params() == d.params() => down = None,
+            ("scale-down" | "scale-up", Some(d)) => {
+                let factor = d.params().unwrap_or_default();
+                return Err(format!(
+                    "effect \"{effect}\" after \"{d}\", expected \"scale-up:{factor}\""
+                ));
+            }
+            ("scale-up", None) => {
+                return Err(format!(
+                    "effect \"{effect}\" without a \"scale-down\" before it"
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    match down {
+        Some(d) => Err(format!(
+            "effect \"{d}\" without a matching \"scale-up:{}\"",
+            d.params().unwrap_or_default()
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect;
+
+    fn pipeline(s: &str) -> Result<Pipeline, Error> {
+        Pipeline::parse(&s.split(',').collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn parse() {
+        let p = pipeline("blur:20,darken:30,pixelate:8,invert,blur:5").unwrap();
+        assert_eq!(
+            format!("{:?}", p.0),
+            r#"["blur:20", "darken:30", "pixelate:8", "invert", "blur:5"]"#
+        );
+        assert!(pipeline("blur").is_err());
+        assert!(pipeline("blur:0").is_err());
+        assert!(pipeline("darken:256").is_err());
+        assert!(pipeline("invert:1").is_err());
+        assert!(pipeline("sharpen:2").is_err());
+    }
+
+    #[test]
+    fn scale_pairs() {
+        assert!(pipeline("scale-down:2,blur:5,scale-up:2,scale-down:4,scale-up:4").is_ok());
+        assert!(pipeline("scale-down:2,blur:5").is_err());
+        assert!(pipeline("scale-up:2").is_err());
+        assert!(pipeline("scale-down:2,scale-up:4").is_err());
+        assert!(pipeline("scale-down:2,scale-down:2,scale-up:2,scale-up:2").is_err());
+    }
+
+    struct Fill(u8);
+
+    impl Effect for Fill {
+        fn name(&self) -> &'static str {
+            "fill"
+        }
+
+        fn params(&self) -> Option<String> {
+            Some(self.0.to_string())
+        }
+
+        fn apply(&self, img: &mut ImgRefMut<BGRA8>) {
+            for px in img.pixels_mut() {
+                px.g = self.0;
+            }
+        }
+    }
+
+    #[test]
+    fn custom_effect() {
+        let mut registry = Registry::builtin();
+        registry.register("fill", |arg| {
+            Ok(Box::new(Fill(effect::param("fill", arg)?)))
+        });
+        let effect = registry.parse("fill:7").unwrap();
+
+        let mut data = vec![BGRA8::default(); 4];
+        let mut img = ImgRefMut::new(data.as_mut(), 2, 2);
+        let mut ran = Vec::new();
+        Pipeline::new(vec![effect])
+            .unwrap()
+            .run(&mut img, |effect, _| ran.push(effect.to_string()));
+        assert_eq!(ran, ["fill:7"]);
+        assert!(data.iter().all(|px| px.g == 7));
+    }
+}
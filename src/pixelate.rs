@@ -0,0 +1,133 @@
+//! Pixelating the screenshot.
+
+use std::num::NonZeroUsize;
+
+use imgref::ImgRefMut;
+
+#[cfg(feature = "threads")]
+use rayon::prelude::*;
+
+use rgb::alt::BGRA8;
+
+/// Pixelation into square blocks.
+pub trait Pixelate {
+    /// Fill each `block` sized square with its average color.
+    fn pixelate(&mut self, block: NonZeroUsize);
+}
+
+impl Pixelate for ImgRefMut<'_, BGRA8> {
+    fn pixelate(&mut self, block: NonZeroUsize) {
+        let block = block.get();
+        let (w, h, stride) = (self.width(), self.height(), self.stride());
+        // only the image's rows, the buffer may run on past the last one
+        let end = (stride * h).saturating_sub(stride - w);
+        let buf = &mut self.buf_mut()[..end];
+
+        #[cfg(not(feature = "threads"))]
+        for rows in buf.chunks_mut(stride * block) {
+            pixelate_rows(rows, w, stride, block);
+        }
+
+        #[cfg(feature = "threads")]
+        buf.par_chunks_mut(stride * block)
+            .for_each(|rows| pixelate_rows(rows, w, stride, block));
+    }
+}
+
+/// Fill each `block` wide cell of `rows` with its average color.
+/// The last cell and the last row may be cut short.
+fn pixelate_rows(rows: &mut [BGRA8], w: usize, stride: usize, block: usize) {
+    let height = rows.len().div_ceil(stride);
+    for x in (0..w).step_by(block) {
+        let cols = x..(x + block).min(w);
+        let cell = || (0..height).flat_map(|y| cols.clone().map(move |x| y * stride + x));
+
+        let (mut b, mut g, mut r, mut a) = (0, 0, 0, 0);
+        for i in cell() {
+            b += usize::from(rows[i].b);
+            g += usize::from(rows[i].g);
+            r += usize::from(rows[i].r);
+            a += usize::from(rows[i].a);
+        }
+
+        let n = height * cols.len();
+        #[allow(clippy::cast_possible_truncation)]
+        let avg = |sum: usize| ((sum + n / 2) / n) as u8;
+        let color = BGRA8 {
+            b: avg(b),
+            g: avg(g),
+            r: avg(r),
+            a: avg(a),
+        };
+        for i in cell() {
+            rows[i] = color;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn gray(v: u8) -> BGRA8 {
+        BGRA8 {
+            b: v,
+            g: v,
+            r: v,
+            a: 255,
+        }
+    }
+
+    #[test]
+    fn pixelate() {
+        #[rustfmt::skip]
+        let mut data = vec![
+            gray(0),  gray(10), gray(20),
+            gray(30), gray(40), gray(50),
+            gray(60), gray(70), gray(80),
+        ];
+        let mut img = ImgRefMut::new(data.as_mut(), 3, 3);
+        img.pixelate(NonZeroUsize::new(2).unwrap());
+        #[rustfmt::skip]
+        assert_eq!(
+            data,
+            [
+                gray(20), gray(20), gray(35),
+                gray(20), gray(20), gray(35),
+                gray(65), gray(65), gray(80),
+            ]
+        );
+    }
+
+    #[test]
+    fn pixelate_stride() {
+        let mut data = vec![gray(0), gray(100), gray(255), gray(50), gray(150)];
+        let mut img = ImgRefMut::new_stride(data.as_mut(), 2, 2, 3);
+        img.pixelate(NonZeroUsize::new(2).unwrap());
+        assert_eq!(data, [gray(75), gray(75), gray(255), gray(75), gray(75)]);
+    }
+
+    #[test]
+    fn pixelate_trailing_buffer() {
+        // 2x2 in a stride of 3, then a spare row and a bit past the image
+        #[rustfmt::skip]
+        let mut data = vec![
+            gray(0),  gray(100), gray(255),
+            gray(50), gray(150), gray(255),
+            gray(255), gray(255), gray(255),
+            gray(255),
+        ];
+        let mut img = ImgRefMut::new_stride(data.as_mut(), 2, 2, 3);
+        img.pixelate(NonZeroUsize::new(4).unwrap());
+        #[rustfmt::skip]
+        assert_eq!(
+            data,
+            [
+                gray(75), gray(75), gray(255),
+                gray(75), gray(75), gray(255),
+                gray(255), gray(255), gray(255),
+                gray(255),
+            ]
+        );
+    }
+}
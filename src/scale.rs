@@ -1,3 +1,5 @@
+//! Scaling the screenshot down and back up.
+
 use std::num::NonZeroUsize;
 
 use imgref::ImgExt;
@@ -5,8 +7,19 @@ use imgref::ImgRefMut;
 
 use itertools::iproduct;
 
+/// Nearest-neighbor scaling in place, to make blurring cheaper.
 pub trait Scale {
+    /// Stretch the top-left `1/factor` of the image back over all of it.
+    ///
+    /// # Safety
+    ///
+    /// Must follow [`scale_down`](Scale::scale_down) with the same `factor`.
     unsafe fn scale_up(&mut self, factor: NonZeroUsize);
+    /// Shrink the image into its top-left `1/factor`, leaving the rest undefined.
+    ///
+    /// # Safety
+    ///
+    /// The image is garbage until [`scale_up`](Scale::scale_up) is called with the same `factor`.
     unsafe fn scale_down(&mut self, factor: NonZeroUsize);
 }
 
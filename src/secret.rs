@@ -0,0 +1,244 @@
+//! Keeping what was on screen out of swap, and wiping it once the locker has it.
+
+use std::cell::Cell;
+use std::io::{self, Write};
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::sync::atomic::{compiler_fence, Ordering};
+
+use rgb::alt::BGRA8;
+
+/// Called with each buffer once it's wiped.
+type Hook = fn(&[u8]);
+
+thread_local! {
+    static HOOK: Cell<Option<Hook>> = const { Cell::new(None) };
+}
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for u8 {}
+    impl Sealed for rgb::alt::BGRA8 {}
+}
+
+/// Plain bytes or pixels, with no padding, that are all zero bits once wiped.
+pub trait Plain: Copy + sealed::Sealed {}
+
+impl Plain for u8 {}
+impl Plain for BGRA8 {}
+
+fn bytes_mut<T: Plain>(buf: &mut [T]) -> &mut [u8] {
+    // SAFETY: Plain types have no padding, and any bytes are a valid value
+    unsafe { std::slice::from_raw_parts_mut(buf.as_mut_ptr().cast(), std::mem::size_of_val(buf)) }
+}
+
+/// Lock `buf` into memory so it's never written to swap.
+///
+/// Fails for buffers over `RLIMIT_MEMLOCK`, which is often only 8 MiB.
+#[cfg(feature = "mlock")]
+pub fn lock<T>(buf: &[T]) -> io::Result<()> {
+    if buf.is_empty() {
+        return Ok(());
+    }
+    // SAFETY: the range is a live allocation, and mlock doesn't touch its contents
+    if unsafe { libc::mlock(buf.as_ptr().cast(), std::mem::size_of_val(buf)) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Lock `buf` into memory so it's never written to swap.
+#[cfg(not(feature = "mlock"))]
+pub fn lock<T>(_: &[T]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "feature \"mlock\" was not enabled at compile-time",
+    ))
+}
+
+/// Undo [`lock`].
+pub fn unlock<T>(buf: &[T]) {
+    #[cfg(feature = "mlock")]
+    if !buf.is_empty() {
+        // SAFETY: as for mlock, and unlocking a range that isn't locked is harmless
+        unsafe {
+            libc::munlock(buf.as_ptr().cast(), std::mem::size_of_val(buf));
+        }
+    }
+    #[cfg(not(feature = "mlock"))]
+    let _ = buf;
+}
+
+/// Overwrite `buf` with zeroes, in a way the compiler can't optimize out.
+pub fn wipe<T: Plain>(buf: &mut [T]) {
+    let bytes = bytes_mut(buf);
+    for byte in bytes.iter_mut() {
+        // SAFETY: byte is a valid, aligned reference
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+
+    if let Some(hook) = HOOK.get() {
+        hook(bytes);
+    }
+}
+
+/// Call `hook` with each buffer [`wipe`] zeroes on this thread from now on, so tests
+/// can check what was wiped.
+#[doc(hidden)]
+pub fn set_hook(hook: Option<Hook>) {
+    HOOK.set(hook);
+}
+
+/// A buffer locked into memory if possible, and wiped when dropped.
+#[derive(Debug)]
+pub struct Secret<T: Plain> {
+    buf: Vec<T>,
+    locked: bool,
+}
+
+impl<T: Plain> Secret<T> {
+    /// Take over `buf`.
+    pub fn new(buf: Vec<T>) -> Self {
+        let locked = lock(&buf).is_ok();
+        Self { buf, locked }
+    }
+
+    /// Whether the buffer is locked into memory.
+    pub const fn locked(&self) -> bool {
+        self.locked
+    }
+}
+
+impl<T: Plain> Deref for Secret<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.buf
+    }
+}
+
+impl<T: Plain> DerefMut for Secret<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.buf
+    }
+}
+
+impl<T: Plain> AsRef<[T]> for Secret<T> {
+    fn as_ref(&self) -> &[T] {
+        &self.buf
+    }
+}
+
+impl<T: Plain> Drop for Secret<T> {
+    fn drop(&mut self) {
+        wipe(&mut self.buf);
+        if self.locked {
+            unlock(&self.buf);
+        }
+    }
+}
+
+/// Like [`io::BufWriter`], but buffering in a [`Secret`], so what passes through it
+/// isn't left behind in freed memory.
+pub struct Buffered<W: Write> {
+    inner: W,
+    buf: Secret<u8>,
+    len: usize,
+}
+
+impl<W: Write> Buffered<W> {
+    /// Buffer writes to `inner` 64 KiB at a time.
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            buf: Secret::new(vec![0; 64 * 1024]),
+            len: 0,
+        }
+    }
+
+    fn flush_buf(&mut self) -> io::Result<()> {
+        let len = std::mem::take(&mut self.len);
+        self.inner.write_all(&self.buf[..len])
+    }
+}
+
+impl<W: Write> Write for Buffered<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.len == self.buf.len() {
+            self.flush_buf()?;
+        }
+        let n = data.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&data[..n]);
+        self.len += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_buf()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for Buffered<W> {
+    fn drop(&mut self) {
+        // like BufWriter, errors can't be reported from here
+        let _ = self.flush_buf();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    thread_local! {
+        static WIPED: Cell<(usize, bool)> = const { Cell::new((0, true)) };
+    }
+
+    /// Count what [`wipe`] zeroes on this thread, and whether it was all zero.
+    pub(crate) fn watch() {
+        WIPED.set((0, true));
+        set_hook(Some(|bytes| {
+            let (len, zero) = WIPED.get();
+            WIPED.set((len + bytes.len(), zero && bytes.iter().all(|&b| b == 0)));
+        }));
+    }
+
+    /// Bytes wiped since [`watch`], if they were all zeroed.
+    pub(crate) fn wiped() -> Option<usize> {
+        set_hook(None);
+        let (len, zero) = WIPED.get();
+        zero.then_some(len)
+    }
+
+    #[test]
+    fn wipe_on_drop() {
+        watch();
+        let secret = Secret::new(vec![BGRA8::new(1, 2, 3, 4); 16]);
+        assert_eq!(secret[15], BGRA8::new(1, 2, 3, 4));
+        drop(secret);
+        assert_eq!(wiped(), Some(64));
+    }
+
+    #[test]
+    fn buffered() {
+        let mut out = Vec::new();
+        let mut writer = Buffered::new(&mut out);
+        writer.write_all(&[7; 100_000]).unwrap();
+        writer.write_all(b"end").unwrap();
+        watch();
+        drop(writer);
+        assert_eq!(wiped(), Some(64 * 1024));
+        assert_eq!(out.len(), 100_003);
+        assert!(out.ends_with(b"\x07end"));
+    }
+
+    #[test]
+    fn wipe_in_place() {
+        let mut buf = *b"hunter2";
+        watch();
+        wipe(&mut buf);
+        assert_eq!(wiped(), Some(7));
+        assert_eq!(buf, [0; 7]);
+    }
+}
@@ -0,0 +1,258 @@
+//! `--text` templates with placeholders such as `{time}` and `{monitor}`.
+
+use std::ffi::{CStr, CString};
+use std::fmt::Write;
+use std::io;
+use std::{fs, mem, ptr};
+
+use libc::c_char;
+
+use crate::Error;
+
+const PLACEHOLDERS: &str = "time, date, user, realname, host, monitor, uptime";
+
+#[derive(Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Time(CString),
+    User,
+    RealName,
+    Host,
+    Monitor,
+    Uptime,
+}
+
+/// Text with `{placeholders}` that are filled in when the screenshot is processed.
+///
+/// `{time}` and `{date}` take an optional strftime format, e.g. `{time:%H:%M:%S}`.
+/// `{{` and `}}` are literal braces, and `\n` is a line break.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Template(Vec<Part>);
+
+impl Template {
+    /// Parse `s`, failing on unknown placeholders and unbalanced braces.
+    pub fn parse(s: &str) -> Result<Self, Error> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '\\' if chars.as_str().starts_with('n') => {
+                    chars.next();
+                    literal.push('\n');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| Error::Template(format!("unclosed \"{{\" in \"{s}\"")))?;
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(mem::take(&mut literal)));
+                    }
+                    parts.push(Self::placeholder(&rest[..end])?);
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => {
+                    return Err(Error::Template(format!(
+                        "unmatched \"}}\" in \"{s}\", use \"}}}}\""
+                    )))
+                }
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self(parts))
+    }
+
+    fn placeholder(s: &str) -> Result<Part, Error> {
+        let (name, format) = match s.split_once(':') {
+            Some((name, format)) => (name, Some(format)),
+            None => (s, None),
+        };
+
+        let time = |default| {
+            CString::new(format.unwrap_or(default))
+                .map(Part::Time)
+                .map_err(|e| Error::Template(format!("placeholder \"{{{s}}}\": {e}")))
+        };
+        let part = match name {
+            "time" => time("%H:%M")?,
+            "date" => time("%Y-%m-%d")?,
+            "user" => Part::User,
+            "realname" => Part::RealName,
+            "host" => Part::Host,
+            "monitor" => Part::Monitor,
+            "uptime" => Part::Uptime,
+            _ => {
+                return Err(Error::Template(format!(
+                    "unknown placeholder \"{{{s}}}\", expected one of: {PLACEHOLDERS}"
+                )))
+            }
+        };
+
+        if format.is_some() && !matches!(part, Part::Time(_)) {
+            return Err(Error::Template(format!(
+                "placeholder \"{{{name}}}\" doesn't take a format"
+            )));
+        }
+        Ok(part)
+    }
+
+    /// Fill in every placeholder, with `monitor` as the RandR output name.
+    pub fn expand(&self, monitor: &str) -> io::Result<String> {
+        let mut s = String::new();
+        for part in &self.0 {
+            match part {
+                Part::Literal(literal) => s.push_str(literal),
+                Part::Time(format) => s.push_str(&strftime(format)?),
+                Part::User => s.push_str(&passwd()?.0),
+                Part::RealName => s.push_str(&passwd()?.1),
+                Part::Host => s.push_str(&hostname()?),
+                Part::Monitor => s.push_str(monitor),
+                Part::Uptime => {
+                    let uptime = fs::read_to_string("/proc/uptime")?;
+                    let secs = uptime
+                        .split('.')
+                        .next()
+                        .and_then(|secs| secs.parse().ok())
+                        .ok_or_else(|| io::Error::other("malformed /proc/uptime"))?;
+                    s.push_str(&fmt_uptime(secs));
+                }
+            }
+        }
+        Ok(s)
+    }
+}
+
+fn strftime(format: &CStr) -> io::Result<String> {
+    let mut buf = [0 as c_char; 256];
+    // SAFETY: tm is plain data filled in by localtime_r, and strftime
+    // writes at most buf.len() bytes including the trailing NUL
+    let len = unsafe {
+        let now = libc::time(ptr::null_mut());
+        let mut tm = mem::zeroed::<libc::tm>();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        libc::strftime(buf.as_mut_ptr(), buf.len(), format.as_ptr(), &tm)
+    };
+    let bytes = buf[..len].iter().map(|&c| c as u8).collect::<Vec<_>>();
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// The current user's login name and real name, from the passwd database.
+/// The real name falls back to the login name if GECOS is empty.
+fn passwd() -> io::Result<(String, String)> {
+    let mut buf = vec![0 as c_char; 4096];
+    // SAFETY: the strings in pwd point into buf, which outlives them
+    unsafe {
+        let mut pwd = mem::zeroed::<libc::passwd>();
+        let mut result = ptr::null_mut();
+        let rc = libc::getpwuid_r(
+            libc::getuid(),
+            &mut pwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        );
+        if result.is_null() {
+            return Err(if rc == 0 {
+                io::Error::new(io::ErrorKind::NotFound, "no passwd entry for current user")
+            } else {
+                io::Error::from_raw_os_error(rc)
+            });
+        }
+
+        let user = CStr::from_ptr(pwd.pw_name).to_string_lossy().into_owned();
+        let gecos = if pwd.pw_gecos.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(pwd.pw_gecos).to_string_lossy().into_owned()
+        };
+        let realname = match gecos.split(',').next() {
+            Some(name) if !name.is_empty() => name.to_owned(),
+            _ => user.clone(),
+        };
+        Ok((user, realname))
+    }
+}
+
+fn hostname() -> io::Result<String> {
+    let mut buf = [0 as c_char; 256];
+    // SAFETY: gethostname NUL terminates within buf.len() on success, and
+    // the last byte is never written so CStr always finds a NUL
+    unsafe {
+        if libc::gethostname(buf.as_mut_ptr(), buf.len() - 1) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned())
+    }
+}
+
+fn fmt_uptime(secs: u64) -> String {
+    let (days, hours, mins) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    let mut s = String::new();
+    if days > 0 {
+        let _ = write!(s, "{days}d ");
+    }
+    if days > 0 || hours > 0 {
+        let _ = write!(s, "{hours}h ");
+    }
+    let _ = write!(s, "{mins}m");
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let t = Template::parse("Locked {{{user}}} on {monitor}\\n{time:%H}").unwrap();
+        assert_eq!(
+            t.0,
+            [
+                Part::Literal("Locked {".into()),
+                Part::User,
+                Part::Literal("} on ".into()),
+                Part::Monitor,
+                Part::Literal("\n".into()),
+                Part::Time(CString::new("%H").unwrap()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let e = Template::parse("{nope}").unwrap_err().to_string();
+        assert!(e.contains("{nope}"), "{e}");
+        assert!(Template::parse("{user").is_err());
+        assert!(Template::parse("user}").is_err());
+        assert!(Template::parse("{host:%H}").is_err());
+    }
+
+    #[test]
+    fn expand() {
+        let t = Template::parse("{monitor}: {date:%%}").unwrap();
+        assert_eq!(t.expand("eDP-1").unwrap(), "eDP-1: %");
+    }
+
+    #[test]
+    fn uptime() {
+        assert_eq!(fmt_uptime(59), "0m");
+        assert_eq!(fmt_uptime(3 * 3600 + 120), "3h 2m");
+        assert_eq!(fmt_uptime(86400 + 60), "1d 0h 1m");
+    }
+}
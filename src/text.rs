@@ -0,0 +1,256 @@
+//! Rendering `--text` with an outline and drop shadow.
+
+use std::fs;
+use std::path::Path;
+
+use ab_glyph::{point, Font, FontVec, Glyph, PxScale, ScaleFont};
+
+use imgref::ImgVec;
+
+use rgb::alt::BGRA8;
+
+use crate::Error;
+
+/// How to draw text.
+pub struct TextStyle {
+    /// Font size in pixels
+    pub size: f32,
+    /// Fill color
+    pub color: BGRA8,
+    /// Opacity of the whole layer, including outline and shadow
+    pub alpha: u8,
+    /// Outline width in pixels and color
+    pub outline: Option<(usize, BGRA8)>,
+    /// Shadow offset in pixels and color
+    pub shadow: Option<(isize, isize, BGRA8)>,
+}
+
+/// A font loaded with a style, ready to render.
+pub struct Text {
+    font: FontVec,
+    style: TextStyle,
+}
+
+impl Text {
+    /// Load the TrueType or OpenType font at `font`.
+    pub fn new(font: &Path, style: TextStyle) -> Result<Self, Error> {
+        let font = fs::read(font)
+            .map_err(|e| e.to_string())
+            .and_then(|data| FontVec::try_from_vec(data).map_err(|e| e.to_string()))
+            .map_err(|e| Error::Font(format!("{}: {e}", font.display())))?;
+        Ok(Self { font, style })
+    }
+
+    /// Rasterize `s` onto a transparent layer just large enough to hold it,
+    /// including the outline and shadow. Lines are centered on the widest.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn render(&self, s: &str) -> ImgVec<BGRA8> {
+        let scaled = self.font.as_scaled(PxScale::from(self.style.size));
+        let line_height = scaled.height() + scaled.line_gap();
+
+        let lines = s
+            .lines()
+            .enumerate()
+            .map(|(n, line)| {
+                let mut caret = point(0.0, scaled.ascent() + n as f32 * line_height);
+                let mut last = None;
+                let glyphs = line
+                    .chars()
+                    .map(|c| {
+                        let id = scaled.glyph_id(c);
+                        if let Some(last) = last {
+                            caret.x += scaled.kern(last, id);
+                        }
+                        let glyph = id.with_scale_and_position(scaled.scale(), caret);
+                        caret.x += scaled.h_advance(id);
+                        last = Some(id);
+                        glyph
+                    })
+                    .collect::<Vec<Glyph>>();
+                (glyphs, caret.x)
+            })
+            .collect::<Vec<_>>();
+
+        let text_w = lines.iter().map(|(_, w)| *w).fold(0.0, f32::max).ceil() as usize;
+        let text_h = (lines.len() as f32 * line_height - scaled.line_gap())
+            .ceil()
+            .max(0.0) as usize;
+        if text_w == 0 || text_h == 0 {
+            return ImgVec::new(Vec::new(), 0, 0);
+        }
+
+        // leave room for the outline all around and the shadow on one side
+        let border = self.style.outline.map_or(0, |(px, _)| px);
+        let (dx, dy) = self.style.shadow.map_or((0, 0), |(x, y, _)| (x, y));
+        let w = text_w + 2 * border + dx.unsigned_abs();
+        let h = text_h + 2 * border + dy.unsigned_abs();
+        let ox = border + usize::try_from(-dx).unwrap_or(0);
+        let oy = border + usize::try_from(-dy).unwrap_or(0);
+
+        let mut fill = vec![0.0; w * h];
+        for (glyphs, line_w) in lines {
+            let indent = (text_w as f32 - line_w) / 2.0;
+            for mut glyph in glyphs {
+                glyph.position.x += indent + ox as f32;
+                glyph.position.y += oy as f32;
+                let Some(outlined) = self.font.outline_glyph(glyph) else {
+                    continue;
+                };
+                let bounds = outlined.px_bounds();
+                outlined.draw(|x, y, c| {
+                    let x = bounds.min.x as isize + x as isize;
+                    let y = bounds.min.y as isize + y as isize;
+                    if let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y)) {
+                        if x < w && y < h {
+                            let px = &mut fill[y * w + x];
+                            *px = (*px + c).min(1.0);
+                        }
+                    }
+                });
+            }
+        }
+
+        let outline = self.style.outline.map(|(px, _)| dilate(&fill, w, h, px));
+
+        let mut layer = vec![BGRA8::default(); w * h];
+        if let Some((dx, dy, color)) = self.style.shadow {
+            let shadow = shift(outline.as_ref().unwrap_or(&fill), w, h, dx, dy);
+            paint(&mut layer, &shadow, color);
+        }
+        if let (Some(outline), Some((_, color))) = (&outline, self.style.outline) {
+            paint(&mut layer, outline, color);
+        }
+        paint(&mut layer, &fill, self.style.color);
+
+        if self.style.alpha < 255 {
+            for px in &mut layer {
+                px.a = (u16::from(px.a) * u16::from(self.style.alpha) / 255) as u8;
+            }
+        }
+
+        ImgVec::new(layer, w, h)
+    }
+}
+
+/// Convert an RGBA color from the command line.
+pub const fn bgra([r, g, b, a]: [u8; 4]) -> BGRA8 {
+    BGRA8 { b, g, r, a }
+}
+
+/// Offset of a layer `size` long along a monitor edge `len` long, from the start
+/// for positive `pos` and from the end for negative `pos`. Clamped to the monitor.
+pub const fn place(pos: isize, size: usize, len: usize) -> usize {
+    let max = len.saturating_sub(size);
+    let pos_abs = pos.unsigned_abs();
+    let pos_abs = if pos_abs < max { pos_abs } else { max };
+    if pos < 0 {
+        max - pos_abs
+    } else {
+        pos_abs
+    }
+}
+
+/// Grow the coverage `mask` by `r` pixels in every direction.
+fn dilate(mask: &[f32], w: usize, h: usize, r: usize) -> Vec<f32> {
+    let mut out = mask.to_vec();
+    for (i, &c) in mask.iter().enumerate().filter(|(_, &c)| c > 0.0) {
+        let (x, y) = (i % w, i / w);
+        for ny in y.saturating_sub(r)..(y + r + 1).min(h) {
+            for nx in x.saturating_sub(r)..(x + r + 1).min(w) {
+                if nx.abs_diff(x).pow(2) + ny.abs_diff(y).pow(2) <= r * r {
+                    let px = &mut out[ny * w + nx];
+                    *px = px.max(c);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Move the coverage `mask` by (`dx`, `dy`), dropping whatever falls off the edge.
+fn shift(mask: &[f32], w: usize, h: usize, dx: isize, dy: isize) -> Vec<f32> {
+    let mut out = vec![0.0; mask.len()];
+    for (i, &c) in mask.iter().enumerate() {
+        let x = (i % w).checked_add_signed(dx);
+        let y = (i / w).checked_add_signed(dy);
+        if let (Some(x), Some(y)) = (x, y) {
+            if x < w && y < h {
+                out[y * w + x] = c;
+            }
+        }
+    }
+    out
+}
+
+/// Paint `color` through the coverage `mask` over `layer`, both in straight alpha.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn paint(layer: &mut [BGRA8], mask: &[f32], color: BGRA8) {
+    for (dst, &c) in layer.iter_mut().zip(mask).filter(|(_, &c)| c > 0.0) {
+        let src_a = f32::from(color.a) / 255.0 * c;
+        let dst_a = f32::from(dst.a) / 255.0 * (1.0 - src_a);
+        let out_a = src_a + dst_a;
+        let mix =
+            |s: u8, d: u8| ((f32::from(s) * src_a + f32::from(d) * dst_a) / out_a).round() as u8;
+        *dst = BGRA8 {
+            b: mix(color.b, dst.b),
+            g: mix(color.g, dst.g),
+            r: mix(color.r, dst.r),
+            a: (out_a * 255.0).round() as u8,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn place_clamps() {
+        assert_eq!(place(20, 100, 1920), 20);
+        assert_eq!(place(-20, 100, 1920), 1800);
+        assert_eq!(place(5000, 100, 1920), 1820);
+        assert_eq!(place(-5000, 100, 1920), 0);
+        assert_eq!(place(10, 3000, 1920), 0);
+    }
+
+    #[test]
+    fn outline_and_shadow() {
+        let mut mask = vec![0.0; 25];
+        mask[12] = 1.0;
+        let outline = dilate(&mask, 5, 5, 1);
+        assert_eq!(outline.iter().filter(|&&c| c == 1.0).count(), 5);
+        let shadow = shift(&outline, 5, 5, 2, 2);
+        assert_eq!(shadow[24], 1.0);
+        assert_eq!(shadow[12], 0.0);
+    }
+
+    #[test]
+    fn paint_over() {
+        let mut layer = [BGRA8::default(); 2];
+        let red = BGRA8 {
+            b: 0,
+            g: 0,
+            r: 255,
+            a: 255,
+        };
+        let blue = BGRA8 {
+            b: 255,
+            g: 0,
+            r: 0,
+            a: 255,
+        };
+        paint(&mut layer, &[1.0, 0.0], red);
+        paint(&mut layer, &[0.5, 0.5], blue);
+        assert_eq!(layer[0].a, 255);
+        assert_eq!((layer[0].r, layer[0].b), (128, 128));
+        assert_eq!(
+            layer[1],
+            BGRA8 {
+                b: 255,
+                g: 0,
+                r: 0,
+                a: 128
+            }
+        );
+    }
+}